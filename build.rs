@@ -90,13 +90,27 @@ mod download {
     }
 
     pub(crate) fn start() {
+        assert!(
+            SUPPORTED_VERSIONS.contains(&VERSION),
+            "{} missing from SUPPORTED_VERSIONS",
+            VERSION
+        );
         let download_filename = download_filename();
         let expected_hash = get_expected_sha256(&download_filename);
-        let out_dir = std::env::var_os("OUT_DIR").unwrap();
-        let mut bitcoin_exe_home = Path::new(&out_dir).join("bitcoin");
+        println!("cargo:rerun-if-changed=build.rs");
+        println!("cargo:rerun-if-env-changed=BITCOIND_DOWNLOAD_DIR");
+        // a cache dir outside the build directory survives `cargo clean` and is shared by projects
+        let mut bitcoin_exe_home = match std::env::var_os("BITCOIND_DOWNLOAD_DIR") {
+            Some(cache_dir) => Path::new(&cache_dir).to_path_buf(),
+            None => Path::new(&std::env::var_os("OUT_DIR").unwrap()).join("bitcoin"),
+        };
         if !bitcoin_exe_home.exists() {
-            std::fs::create_dir(&bitcoin_exe_home).unwrap();
+            std::fs::create_dir_all(&bitcoin_exe_home).unwrap();
         }
+        println!(
+            "cargo:rustc-env=BITCOIND_EXE_HOME={}",
+            bitcoin_exe_home.display()
+        );
         let existing_filename = bitcoin_exe_home
             .join(format!("bitcoin-{}", VERSION))
             .join("bin")
@@ -1,20 +1,39 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![cfg_attr(feature = "doc", cfg_attr(all(), doc = include_str!("../README.md")))]
 
+mod proxy;
 mod versions;
 
-use crate::bitcoincore_rpc::jsonrpc::serde_json::Value;
+use crate::bitcoincore_rpc::jsonrpc::serde_json::{self, from_value, Value};
 use anyhow::Context;
+use bitcoincore_rpc::bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::hashes::hex::ToHex;
+use bitcoincore_rpc::bitcoin::hashes::{hmac, sha256, sha256d, Hash, HashEngine};
+use bitcoincore_rpc::bitcoin::util::amount::serde::as_btc;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, BlockHash, Denomination, OutPoint, PackedLockTime, Sequence, Transaction,
+    TxIn, TxOut, Txid, Wtxid,
+};
+use bitcoincore_rpc::json::{
+    GetBlockFilterResult, GetRawTransactionResultVin, GetRawTransactionResultVout, GetTxOutResult,
+    ListBannedResult, ListTransactionResult,
+};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
 use log::{debug, error, warn};
-use std::ffi::OsStr;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use std::{env, fmt, fs, thread};
 use tempfile::TempDir;
 
+pub use crate::versions::SUPPORTED_VERSIONS;
 pub use anyhow;
 pub use bitcoincore_rpc;
 pub use tempfile;
@@ -23,17 +42,296 @@ pub use which;
 #[derive(Debug)]
 /// Struct representing the bitcoind process with related information
 pub struct BitcoinD {
-    /// Process child handle, used to terminate the process when this struct is dropped
-    process: Child,
+    /// Process child handle, used to terminate the process when this struct is dropped. In a
+    /// mutex so that [BitcoinD::is_running] can poll it from a shared reference
+    process: Mutex<Child>,
+    /// How the process has been launched, used to launch it again in [BitcoinD::restart]
+    launch: Launch,
     /// Rpc client linked to this bitcoind process
+    ///
+    /// The client can be shared between threads: every request opens its own connection, so
+    /// concurrent calls are not serialized on the client side. On the node side requests are
+    /// served by a pool of `-rpcthreads` workers (4 by default), see [Conf::rpc_threads] to
+    /// raise it when load testing.
     pub client: Client,
     /// Work directory, where the node store blocks and other stuff.
     work_dir: DataDir,
 
     /// Contains information to connect to this node
     pub params: ConnectParams,
+
+    /// Addresses generated at startup, handed out by [BitcoinD::next_address]
+    address_pool: Mutex<VecDeque<Address>>,
+
+    /// Whether the data directory is kept when dropped while panicking, see
+    /// [Conf::keep_datadir_on_panic]
+    keep_datadir_on_panic: bool,
+}
+
+/// Node process launched but not ready yet, see [BitcoinD::with_conf] and
+/// [BitcoinD::restart_with_args]
+struct Starting {
+    process: Child,
+    launch: Launch,
+    params: ConnectParams,
+    rpc_url: String,
+    start: Instant,
+    /// Interval waited before the last readiness check
+    poll_interval: Duration,
+    attempts: usize,
+}
+
+/// Outcome of a readiness check of a starting node, see [Starting::step]
+enum StartupStep {
+    /// The node is not ready yet, check again after the given interval
+    Wait(Duration),
+    /// The node is ready, with the client of the configured wallet
+    Ready(Client),
+    /// The process exited early or it wasn't ready in time, launch it again with other ports
+    Retry,
+}
+
+impl Starting {
+    fn new(process: Child, launch: Launch, params: ConnectParams) -> Self {
+        Starting {
+            process,
+            rpc_url: format!("http://{}", params.rpc_socket),
+            poll_interval: launch.startup.poll_interval,
+            launch,
+            params,
+            start: Instant::now(),
+            attempts: 0,
+        }
+    }
+
+    /// Waits until the node is ready returning the client of the configured wallet, `None` if the
+    /// launch must be retried, see [StartupStep::Retry]
+    fn wait_ready(&mut self) -> anyhow::Result<Option<Client>> {
+        let mut wait = self.poll_interval;
+        loop {
+            thread::sleep(wait);
+            match self.step()? {
+                StartupStep::Wait(next) => wait = next,
+                StartupStep::Ready(client) => return Ok(Some(client)),
+                StartupStep::Retry => return Ok(None),
+            }
+        }
+    }
+
+    /// Checks once whether the node is ready, creating or loading the wallet when it is. Blocks
+    /// on RPC calls but never waits between checks, the caller does it.
+    fn step(&mut self) -> anyhow::Result<StartupStep> {
+        let startup = &self.launch.startup;
+        if let Some(status) = self.process.try_wait()? {
+            if startup.max_port_retries > 0 {
+                warn!("early exit with: {:?}. Trying to launch again ({} attempts remaining), maybe some other process used our available port", status, startup.max_port_retries);
+                return Ok(StartupStep::Retry);
+            } else {
+                error!("early exit with: {:?}", status);
+                return Err(self.launch.startup_error(Error::EarlyExit(status)));
+            }
+        }
+        if let Some(timeout) = startup.timeout {
+            if self.start.elapsed() > timeout {
+                let _ = self.process.kill();
+                let _ = self.process.wait();
+                if startup.max_port_retries > 0 {
+                    warn!(
+                        "not ready after {:?}. Trying to launch again ({} attempts remaining)",
+                        timeout, startup.max_port_retries
+                    );
+                    return Ok(StartupStep::Retry);
+                } else {
+                    error!("not ready after {:?}", timeout);
+                    return Err(self.launch.startup_error(Error::StartupTimeout(timeout)));
+                }
+            }
+        }
+        // the cookie is written when the RPC server starts, no point in trying before
+        let client_result = if self.params.credentials_ready() {
+            self.params.client(&self.rpc_url).ok()
+        } else {
+            None
+        };
+
+        if let Some(client_base) = client_result {
+            if node_ready(&client_base, startup) {
+                return match &self.launch.wallet {
+                    Some(wallet) => {
+                        create_or_load_wallet(&client_base, wallet, self.launch.descriptors)?;
+                        let wallet_url = format!("{}/wallet/{}", self.rpc_url, wallet);
+                        let client = self.params.client(&wallet_url).map_err(Error::Rpc)?;
+                        Ok(StartupStep::Ready(client))
+                    }
+                    None => Ok(StartupStep::Ready(client_base)),
+                };
+            }
+        }
+
+        debug!(
+            "bitcoin client for process {} not ready ({})",
+            self.process.id(),
+            self.attempts
+        );
+        self.attempts += 1;
+        self.poll_interval = startup.next_poll_interval(self.poll_interval);
+        Ok(StartupStep::Wait(self.poll_interval))
+    }
+
+    /// Completes the launch of the ready node, `client` is the one returned by [Starting::step]
+    fn finish(self, client: Client, work_dir: DataDir, conf: &Conf) -> anyhow::Result<BitcoinD> {
+        let address_pool = (0..conf.bootstrap_addresses)
+            .map(|_| client.get_new_address(None, None))
+            .collect::<Result<_, _>>()?;
+
+        Ok(BitcoinD {
+            process: Mutex::new(self.process),
+            launch: self.launch,
+            client,
+            work_dir,
+            params: self.params,
+            address_pool: Mutex::new(address_pool),
+            keep_datadir_on_panic: conf.keep_datadir_on_panic
+                || env::var_os("BITCOIND_KEEP_ON_PANIC").is_some(),
+        })
+    }
+}
+
+/// Executable and arguments the node process is launched with
+#[derive(Debug, Clone)]
+struct Launch {
+    exe: OsString,
+    /// All the arguments except `-rpcport`, which may change between launches
+    args: Vec<String>,
+    output: Output,
+    /// Path of the file the output is written to with [Output::File]
+    output_file: PathBuf,
+    log_tee: Option<PathBuf>,
+    startup: StartupPolicy,
+    /// Wallet created or loaded at startup, `None` if the client connects to the node endpoint
+    wallet: Option<String>,
+    /// Whether wallets are created as descriptor wallets, see [Conf::wallet_descriptors]
+    descriptors: Option<bool>,
+    /// Environment variables set in addition to the inherited ones, see [Conf::envs]
+    envs: Vec<(OsString, OsString)>,
+    /// Path of the `bitcoin.conf` holding the arguments, see [Conf::write_conf_file]
+    conf_file: Option<PathBuf>,
+    network: Network,
+    /// Options reserved in addition to [RESERVED_OPTIONS], see [Conf::reserved_options]
+    reserved: Vec<&'static str>,
+}
+
+impl Launch {
+    /// Spawn the node process listening for RPC calls on `rpc_port`, when `append_log` is true
+    /// the output is appended to the [Conf::log_tee] or [Output::File] file instead of
+    /// overwriting it. The `extra_args` are given to this process only.
+    fn spawn(&self, rpc_port: u16, append_log: bool, extra_args: &[&str]) -> anyhow::Result<Child> {
+        let open = |path: &Path| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(append_log)
+                .truncate(!append_log)
+                .open(path)
+        };
+        let tee_file = match &self.log_tee {
+            Some(path) => Some(open(path)?),
+            None => None,
+        };
+        // stderr is never piped: nobody would read the pipe and the node would block once full
+        let (stdout, stderr) = if tee_file.is_some() {
+            (Stdio::piped(), Stdio::inherit())
+        } else {
+            match self.output {
+                Output::Inherit => (Stdio::inherit(), Stdio::inherit()),
+                Output::Null => (Stdio::null(), Stdio::inherit()),
+                Output::File => {
+                    let file = open(&self.output_file)?;
+                    (Stdio::from(file.try_clone()?), Stdio::from(file))
+                }
+            }
+        };
+        let mut command = Command::new(&self.exe);
+        match &self.conf_file {
+            Some(path) => {
+                // rewritten at every launch since the rpc port may change on restart
+                fs::write(path, self.conf_file_content(rpc_port))?;
+                command
+                    .args(self.args.iter().filter(|arg| arg.starts_with("-datadir=")))
+                    .arg(format!("-conf={}", path.display()))
+            }
+            None => command
+                .arg(format!("-rpcport={}", rpc_port))
+                .args(&self.args),
+        };
+        let mut process = command
+            .args(extra_args)
+            .envs(self.envs.iter().map(|(key, value)| (key, value)))
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .with_context(|| format!("Error while executing {:?}", self.exe))?;
+        if let (Some(file), Some(output)) = (tee_file, process.stdout.take()) {
+            tee(output, file);
+        }
+        Ok(process)
+    }
+
+    /// Content of the `bitcoin.conf` equivalent to the arguments, the network selector is at top
+    /// level while everything else is in the network section, where bitcoind reads the
+    /// network-specific options like ports and bind addresses
+    fn conf_file_content(&self, rpc_port: u16) -> String {
+        let mut content = String::new();
+        let mut section = format!("\n[{}]\nrpcport={}\n", self.network.section(), rpc_port);
+        for arg in self.args.iter().filter(|arg| !arg.starts_with("-datadir=")) {
+            let line = arg.trim_start_matches('-');
+            let line = if line.contains('=') {
+                line.to_string()
+            } else {
+                format!("{}=1", line)
+            };
+            if arg == self.network.arg() {
+                content.push_str(&line);
+                content.push('\n');
+            } else {
+                section.push_str(&line);
+                section.push('\n');
+            }
+        }
+        content + &section
+    }
+
+    /// Converts an error occurred while launching the node, adding the last lines of the output
+    /// with [Output::File]
+    fn startup_error(&self, error: Error) -> anyhow::Error {
+        let content = match self.output {
+            Output::File => fs::read_to_string(&self.output_file).unwrap_or_default(),
+            Output::Inherit | Output::Null => return error.into(),
+        };
+        let lines: Vec<_> = content.lines().collect();
+        let tail = lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n");
+        anyhow::Error::from(error).context(format!("bitcoind output:\n{}", tail))
+    }
+}
+
+/// Where the stdout and stderr of the node process go, used in [Conf::output]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Output {
+    /// Shown in the stdout and stderr of this process
+    Inherit,
+    /// Stdout is discarded, stderr is shown in the stderr of this process
+    Null,
+    /// Both written to `stdouterr.log` in the data directory, see [BitcoinD::log_contents]. The
+    /// last lines are included in the errors returned when the node fails to start.
+    File,
 }
 
+/// Number of lines of the [Output::File] output included in startup errors
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Name of the file in the data directory the output is written to with [Output::File]
+const OUTPUT_FILE: &str = "stdouterr.log";
+
 #[derive(Debug)]
 /// The DataDir struct defining the kind of data directory the node
 /// will contain. Data directory can be either persistent, or temporary.
@@ -42,20 +340,24 @@ pub enum DataDir {
     Persistent(PathBuf),
     /// Temporary Data Directory
     Temporary(TempDir),
+    /// Caller provided directory, emptied before the node starts and after it's dropped
+    Reused(PathBuf),
 }
 
 impl DataDir {
     /// Return the data directory path
     fn path(&self) -> PathBuf {
         match self {
-            Self::Persistent(path) => path.to_owned(),
+            Self::Persistent(path) | Self::Reused(path) => path.to_owned(),
             Self::Temporary(tmp_dir) => tmp_dir.path().to_path_buf(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-/// Contains all the information to connect to this node
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Contains all the information to connect to this node, it can be serialized to be handed to
+/// other processes, socket addresses and paths are serialized as strings
 pub struct ConnectParams {
     /// Path to the node cookie file, useful for other client to connect to the node
     pub cookie_file: PathBuf,
@@ -63,24 +365,72 @@ pub struct ConnectParams {
     pub rpc_socket: SocketAddrV4,
     /// p2p connection url, is some if the node started with p2p enabled
     pub p2p_socket: Option<SocketAddrV4>,
+    /// Credentials used by [BitcoinD::client], see [ConnectParams::auth]
+    pub rpc_auth: RpcAuth,
+    /// ZMQ endpoint publishing raw blocks (`-zmqpubrawblock`), is some if [Conf::zmq] is true
+    pub zmq_rawblock: Option<SocketAddrV4>,
+    /// ZMQ endpoint publishing raw transactions (`-zmqpubrawtx`), is some if [Conf::zmq] is true
+    pub zmq_rawtx: Option<SocketAddrV4>,
+    /// HTTP proxy the RPC calls of [BitcoinD::client] go through, see [Conf::rpc_proxy]
+    pub rpc_proxy: Option<String>,
 }
 
 impl ConnectParams {
-    /// Parses the cookie file content
-    fn parse_cookie(content: String) -> anyhow::Result<(Option<String>, Option<String>)> {
-        let values: Vec<&str> = content.splitn(2, ':').collect();
-        Ok((Some(values[0].into()), Some(values[1].into())))
-    }
-
     /// Return the user and password values from cookie file
     pub fn get_cookie_values(&self) -> anyhow::Result<(Option<String>, Option<String>)> {
-        let cookie = std::fs::read_to_string(&self.cookie_file)?;
-        self::ConnectParams::parse_cookie(cookie)
+        let (user, password) = self.cookie_values()?;
+        Ok((Some(user), Some(password)))
+    }
+
+    /// Returns the user and password written by the node in the cookie file, to be given to other
+    /// processes connecting to the node. Returns [Error::CookieNotFound] if the node didn't write
+    /// it yet or it's not using cookie authentication, see [Conf::rpc_auth].
+    pub fn cookie_values(&self) -> Result<(String, String), Error> {
+        let content = fs::read_to_string(&self.cookie_file).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                Error::CookieNotFound(self.cookie_file.clone())
+            } else {
+                Error::Io(e)
+            }
+        })?;
+        parse_cookie(&content)
+    }
+
+    /// Returns whether the credentials to authenticate RPC calls are available, the cookie file
+    /// is written by the node only once its RPC server is started
+    fn credentials_ready(&self) -> bool {
+        match self.rpc_auth {
+            RpcAuth::Cookie => self.cookie_file.exists(),
+            RpcAuth::UserPass(_, _) => true,
+        }
+    }
+
+    /// Returns an RPC client connected to `url` with [ConnectParams::auth], through
+    /// [ConnectParams::rpc_proxy] if any
+    fn client(&self, url: &str) -> Result<Client, bitcoincore_rpc::Error> {
+        match &self.rpc_proxy {
+            Some(proxy) => {
+                let transport = proxy::ProxyTransport::new(proxy, url, self.auth())?;
+                let client = bitcoincore_rpc::jsonrpc::Client::with_transport(transport);
+                Ok(Client::from_jsonrpc(client))
+            }
+            None => Client::new(url, self.auth()),
+        }
+    }
+
+    /// Returns the RPC authentication to connect to the node, the cookie file or the user and
+    /// password given in [Conf::rpc_auth]
+    pub fn auth(&self) -> Auth {
+        match &self.rpc_auth {
+            RpcAuth::Cookie => Auth::CookieFile(self.cookie_file.clone()),
+            RpcAuth::UserPass(user, password) => Auth::UserPass(user.clone(), password.clone()),
+        }
     }
 }
 
 /// Enum to specify p2p settings
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum P2P {
     /// the node doesn't open a p2p port and work in standalone mode
     No,
@@ -90,6 +440,380 @@ pub enum P2P {
     /// initialize this with [BitcoinD::p2p_connect] of another node. The `bool` parameter indicates
     /// if the node can accept connection too.
     Connect(SocketAddrV4, bool),
+    /// Like [P2P::Connect] but the node connects to all the given urls, eg. to connect a node to
+    /// many others in a single launch. The `bool` parameter indicates if the node can accept
+    /// connection too.
+    ConnectMany(Vec<SocketAddrV4>, bool),
+}
+
+/// The chain the node runs on, used in [Conf::network]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Network {
+    /// Local chain where blocks can be mined on demand
+    Regtest,
+    /// Signet, the default one or a custom one with [Conf::signet_challenge]
+    Signet,
+    /// Testnet version 3
+    Testnet,
+    /// Testnet version 4, requires bitcoind 28.0 or later
+    Testnet4,
+}
+
+impl Network {
+    /// The bitcoind argument selecting the network
+    pub fn arg(&self) -> &'static str {
+        match self {
+            Network::Regtest => "-regtest",
+            Network::Signet => "-signet",
+            Network::Testnet => "-testnet",
+            Network::Testnet4 => "-testnet4",
+        }
+    }
+
+    /// The subdirectory of the data directory where the node stores the network data, including
+    /// the cookie file
+    pub fn dir(&self) -> &'static str {
+        match self {
+            Network::Regtest => "regtest",
+            Network::Signet => "signet",
+            Network::Testnet => "testnet3",
+            Network::Testnet4 => "testnet4",
+        }
+    }
+
+    /// The name of the `bitcoin.conf` section holding the options of the network
+    fn section(&self) -> &'static str {
+        match self {
+            Network::Regtest => "regtest",
+            Network::Signet => "signet",
+            Network::Testnet => "test",
+            Network::Testnet4 => "testnet4",
+        }
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Regtest
+    }
+}
+
+/// How clients authenticate to the RPC of the node, used in [Conf::rpc_auth]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RpcAuth {
+    /// Use the `.cookie` file bitcoind writes in its data directory
+    Cookie,
+    /// Use static credentials (user, password), given to bitcoind as a hashed `-rpcauth` so that
+    /// the cookie file keeps working alongside
+    UserPass(String, String),
+}
+
+impl Default for RpcAuth {
+    fn default() -> Self {
+        RpcAuth::Cookie
+    }
+}
+
+/// Type of the addresses generated by the wallet, used by [Conf::address_type],
+/// [Conf::change_type] and [BitcoinD::get_new_address_of_type]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AddressType {
+    /// Pay to public key hash
+    Legacy,
+    /// Pay to witness public key hash nested in pay to script hash
+    P2shSegwit,
+    /// Native segwit v0, pay to witness public key hash
+    Bech32,
+    /// Native segwit v1, taproot. Requires bitcoind 22.0 or later and a descriptor wallet
+    Bech32m,
+}
+
+impl AddressType {
+    /// The name of the address type used in bitcoind options and RPCs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressType::Legacy => "legacy",
+            AddressType::P2shSegwit => "p2sh-segwit",
+            AddressType::Bech32 => "bech32",
+            AddressType::Bech32m => "bech32m",
+        }
+    }
+
+    /// The first bitcoind version supporting the address type
+    fn min_version(&self) -> usize {
+        match self {
+            AddressType::Bech32m => 220_000,
+            _ => 0,
+        }
+    }
+}
+
+/// How much additional fee the wallet pays to avoid partial spends of addresses, see
+/// [Conf::max_aps_fee]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MaxApsFee {
+    /// Partial spends are avoided only if `-avoidpartialspends` is set, `-maxapsfee=-1`
+    Disabled,
+    /// Partial spends are avoided only when it doesn't cost additional fee, `-maxapsfee=0`
+    Always,
+    /// Partial spends are avoided when the additional fee is at most the given amount
+    UpTo(Amount),
+}
+
+impl MaxApsFee {
+    /// The value of the `-maxapsfee` option
+    fn arg_value(&self) -> String {
+        match self {
+            MaxApsFee::Disabled => "-1".to_string(),
+            MaxApsFee::Always => "0".to_string(),
+            MaxApsFee::UpTo(amount) => amount.to_string_in(Denomination::Bitcoin),
+        }
+    }
+}
+
+/// A line of the bitcoind `debug.log` file, see [BitcoinD::log_events]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LogEvent {
+    /// ISO 8601 timestamp of the event, eg. `2023-03-24T10:15:42Z`
+    pub timestamp: String,
+    /// Logging category like `net` or `validation`, it is printed only for debug messages by
+    /// recent bitcoind versions and it's `None` otherwise
+    pub category: Option<String>,
+    /// The logged message
+    pub message: String,
+}
+
+impl LogEvent {
+    /// Parses a `debug.log` line, returns `None` if the line doesn't start with a timestamp
+    fn parse(line: &str) -> Option<LogEvent> {
+        let mut split = line.splitn(2, ' ');
+        let timestamp = split.next()?;
+        let bytes = timestamp.as_bytes();
+        if bytes.len() < 20 || bytes[4] != b'-' || bytes[10] != b'T' || !timestamp.ends_with('Z') {
+            return None;
+        }
+        let rest = split.next().unwrap_or("");
+        let (category, message) = if rest.starts_with('[') {
+            match rest.find("] ") {
+                Some(end) => {
+                    // recent versions may print the level too, eg. `[net:debug]`
+                    let category = rest[1..end].split(':').next().unwrap_or("");
+                    (Some(category.to_string()), &rest[end + 2..])
+                }
+                None => (None, rest),
+            }
+        } else {
+            (None, rest)
+        };
+        Some(LogEvent {
+            timestamp: timestamp.to_string(),
+            category,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// A p2p message captured with [Conf::capture_messages], see [BitcoinD::captured_messages]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CapturedMessage {
+    /// When the message was sent or received, since the unix epoch
+    pub time: Duration,
+    /// `true` if the message was sent by the node, `false` if it was received from the peer
+    pub outbound: bool,
+    /// The message type, eg. `version` or `inv`
+    pub msg_type: String,
+    /// The serialized message payload
+    pub payload: Vec<u8>,
+}
+
+impl CapturedMessage {
+    /// Parses the content of a `msgs_recv.dat` or `msgs_sent.dat` capture file, a trailing
+    /// partially written message is ignored
+    fn parse_all(mut bytes: &[u8], outbound: bool) -> Vec<CapturedMessage> {
+        let mut messages = vec![];
+        // 8 bytes of time in microseconds, 12 of message type and 4 of payload length
+        while bytes.len() >= 24 {
+            let mut time = [0u8; 8];
+            time.copy_from_slice(&bytes[..8]);
+            let mut len = [0u8; 4];
+            len.copy_from_slice(&bytes[20..24]);
+            let len = u32::from_le_bytes(len) as usize;
+            if bytes.len() < 24 + len {
+                break;
+            }
+            let msg_type = bytes[8..20]
+                .iter()
+                .take_while(|b| **b != 0)
+                .map(|b| *b as char)
+                .collect();
+            messages.push(CapturedMessage {
+                time: Duration::from_micros(u64::from_le_bytes(time)),
+                outbound,
+                msg_type,
+                payload: bytes[24..24 + len].to_vec(),
+            });
+            bytes = &bytes[24 + len..];
+        }
+        messages
+    }
+}
+
+/// Models the result of `decoderawtransaction`, see [BitcoinD::decode_raw_transaction]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodeRawTransactionResult {
+    /// The transaction id
+    pub txid: Txid,
+    /// The witness transaction id
+    pub hash: Wtxid,
+    /// The serialized transaction size
+    pub size: usize,
+    /// The virtual transaction size, differs from `size` for witness transactions
+    pub vsize: usize,
+    /// The transaction weight
+    pub weight: usize,
+    /// The transaction version
+    pub version: i32,
+    /// The transaction lock time
+    pub locktime: u32,
+    /// The transaction inputs
+    pub vin: Vec<GetRawTransactionResultVin>,
+    /// The transaction outputs
+    pub vout: Vec<GetRawTransactionResultVout>,
+}
+
+impl DecodeRawTransactionResult {
+    fn from_value(mut value: Value) -> Result<Self, serde_json::Error> {
+        let mut field = |key: &str| value[key].take();
+        Ok(DecodeRawTransactionResult {
+            txid: from_value(field("txid"))?,
+            hash: from_value(field("hash"))?,
+            size: from_value(field("size"))?,
+            vsize: from_value(field("vsize"))?,
+            weight: from_value(field("weight"))?,
+            version: from_value(field("version"))?,
+            locktime: from_value(field("locktime"))?,
+            vin: from_value(field("vin"))?,
+            vout: from_value(field("vout"))?,
+        })
+    }
+}
+
+/// Returns the outputs locked by the wallet from the result of `listlockunspent`, see
+/// [BitcoinD::wallet_utxos]
+fn locked_outpoints(value: Value) -> Result<Vec<OutPoint>, serde_json::Error> {
+    let locked: Vec<Value> = from_value(value)?;
+    locked
+        .into_iter()
+        .map(|mut l| {
+            Ok(OutPoint::new(
+                from_value(l["txid"].take())?,
+                from_value(l["vout"].take())?,
+            ))
+        })
+        .collect()
+}
+
+/// Models the result of `getchaintxstats`, see [BitcoinD::chain_tx_stats]
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChainTxStats {
+    /// The timestamp of the final block of the window
+    pub time: u64,
+    /// The total number of transactions in the chain up to the final block of the window
+    pub tx_count: u64,
+    /// The hash of the final block of the window
+    pub window_final_block_hash: BlockHash,
+    /// The number of blocks in the window
+    pub window_block_count: u64,
+    /// The number of transactions in the window, `None` if the window is empty
+    pub window_tx_count: Option<u64>,
+    /// The elapsed time in the window in seconds, `None` if the window is empty
+    pub window_interval: Option<u64>,
+    /// The average number of transactions per second in the window, `None` if the window
+    /// interval is zero
+    pub tx_rate: Option<f64>,
+}
+
+impl ChainTxStats {
+    fn from_value(mut value: Value) -> Result<Self, serde_json::Error> {
+        let mut field = |key: &str| value[key].take();
+        Ok(ChainTxStats {
+            time: from_value(field("time"))?,
+            tx_count: from_value(field("txcount"))?,
+            window_final_block_hash: from_value(field("window_final_block_hash"))?,
+            window_block_count: from_value(field("window_block_count"))?,
+            window_tx_count: from_value(field("window_tx_count"))?,
+            window_interval: from_value(field("window_interval"))?,
+            tx_rate: from_value(field("txrate"))?,
+        })
+    }
+}
+
+/// Version of bitcoind, versions before 22.0 have the leading `0.` dropped, eg. `0.21.1` has
+/// major 21, minor 1 and patch 0. Versions compare in release order.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Version {
+    /// Major version, eg. 23 for 23.1
+    pub major: usize,
+    /// Minor version, eg. 1 for 23.1
+    pub minor: usize,
+    /// Patch version, eg. 1 for 0.19.0.1
+    pub patch: usize,
+}
+
+impl Version {
+    /// Returns the version from the format of the `version` returned by the `getnetworkinfo`
+    /// RPC, eg. `230100` for `23.1`
+    pub fn from_number(number: usize) -> Self {
+        Version {
+            major: number / 10_000,
+            minor: number / 100 % 100,
+            patch: number % 100,
+        }
+    }
+
+    /// Returns the version in the format of the `version` returned by the `getnetworkinfo` RPC,
+    /// eg. `230100` for `23.1`
+    pub fn number(&self) -> usize {
+        self.major * 10_000 + self.minor * 100 + self.patch
+    }
+}
+
+/// Snapshot of the state of the node from `getblockchaininfo`, see [BitcoinD::status]
+#[derive(Debug, PartialEq, Clone)]
+pub struct NodeStatus {
+    /// The network of the node, eg. `regtest`
+    pub chain: String,
+    /// The height of the most-work fully-validated chain
+    pub blocks: u64,
+    /// The height of the most-work header chain
+    pub headers: u64,
+    /// The hash of the tip
+    pub best_block_hash: BlockHash,
+    /// Estimate of the verification progress, between 0 and 1
+    pub verification_progress: f64,
+    /// Whether the node is in initial block download
+    pub ibd: bool,
+    /// The size of the block and undo files on disk in bytes
+    pub size_on_disk: u64,
+    /// Whether the blocks are pruned
+    pub pruned: bool,
+}
+
+impl NodeStatus {
+    fn from_value(mut value: Value) -> Result<Self, serde_json::Error> {
+        let mut field = |key: &str| value[key].take();
+        Ok(NodeStatus {
+            chain: from_value(field("chain"))?,
+            blocks: from_value(field("blocks"))?,
+            headers: from_value(field("headers"))?,
+            best_block_hash: from_value(field("bestblockhash"))?,
+            verification_progress: from_value(field("verificationprogress"))?,
+            ibd: from_value(field("initialblockdownload"))?,
+            size_on_disk: from_value(field("size_on_disk"))?,
+            pruned: from_value(field("pruned"))?,
+        })
+    }
 }
 
 /// All the possible error in this crate
@@ -114,20 +838,116 @@ pub enum Error {
     /// Returned when -rpcuser and/or -rpcpassword is used in `Conf` args
     /// It will soon be deprecated, please use -rpcauth instead
     RpcUserAndPasswordUsed,
+    /// Returned when two nodes expected to be at the same tip have different best block hashes
+    TipMismatch {
+        /// Best block hash of the node the comparison has been called on
+        this: BlockHash,
+        /// Best block hash of the other node
+        other: BlockHash,
+    },
+    /// Returned when an option in `Conf` is not supported by the version of the bitcoind executable
+    UnsupportedVersion {
+        /// The bitcoind argument the option maps to
+        option: &'static str,
+        /// The version of the bitcoind executable, in the same format of the `version` returned
+        /// by the `getnetworkinfo` RPC, eg. `230000` for `23.0`
+        version: usize,
+    },
+    /// Returned when two options in `Conf` cannot be used together
+    ConflictingOptions {
+        /// The first conflicting option
+        option: &'static str,
+        /// The option conflicting with the first one
+        conflicts_with: &'static str,
+    },
+    /// Returned when the node doesn't know the requested softfork
+    UnknownSoftfork(String),
+    /// Returned when a condition waited for is not met within the given timeout
+    Timeout(Duration),
+    /// Returned when the node is not ready to accept RPC calls within [StartupPolicy::timeout]
+    StartupTimeout(Duration),
+    /// Returned when a transaction or a PSBT is missing some signatures
+    IncompleteSignature,
+    /// Returned when the fee of a transaction not in the mempool nor sent by the wallet is asked
+    UnknownFee(Txid),
+    /// Returned when `debug.log` contains an error not allowed, with the offending line
+    UnexpectedLogError(String),
+    /// Returned when a fraction not in the range `(0, 1]` is given
+    InvalidFraction(f64),
+    /// Returned when the requested bitcoind version is not one of [SUPPORTED_VERSIONS]
+    UnknownVersion(String),
+    /// Returned when the requested bitcoind version is not the one downloaded at build time
+    VersionNotDownloaded {
+        /// The requested version
+        requested: String,
+        /// The version downloaded according to the enabled feature
+        downloaded: &'static str,
+    },
+    /// Returned when asking a client for a wallet not loaded in the node
+    WalletNotLoaded(String),
+    /// Returned when `submitblock` doesn't accept the block, with the reason given by the node
+    BlockRejected(String),
+    /// Returned when [Conf::args] or [Conf::set] contain an option managed by this crate, like
+    /// `-rpcport`
+    ReservedOption(String),
+    /// Returned when a transaction expected to be rejected is accepted by the node
+    TxAccepted(Txid),
+    /// Returned when a transaction is rejected with a reason different from the expected one
+    RejectReasonMismatch {
+        /// The expected substring of the reason
+        expected: String,
+        /// The reason given by the node
+        actual: String,
+    },
+    /// Returned when connecting to a node not opening its p2p port, see [P2P]
+    NoP2pPort,
+    /// Returned when [Conf::prune] is below [MIN_PRUNE_TARGET_MIB] and not 1 (manual pruning)
+    InvalidPruneTarget(u32),
+    /// Returned when the node stopped with [BitcoinD::stop_checked] exits with a failure status or
+    /// killed by a signal, with the exit status
+    UncleanExit(ExitStatus),
+    /// Returned when the cookie file at the given path is not written, see
+    /// [ConnectParams::cookie_values]
+    CookieNotFound(PathBuf),
+    /// Returned when the given bitcoind executable doesn't exist, even with the `.exe` suffix on
+    /// Windows, and it's not found in the `PATH`
+    ExeNotFound(PathBuf),
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Io(_) => write!(f, "io::Error"),
-            Error::Rpc(_) => write!(f, "bitcoin_rpc::Error"),
+            Error::Io(e) => write!(f, "io::Error: {}", e),
+            Error::Rpc(e) => write!(f, "bitcoin_rpc::Error: {}", e),
             Error::NoFeature => write!(f, "Called a method requiring a feature to be set, but it's not"),
             Error::NoEnvVar => write!(f, "Called a method requiring env var `BITCOIND_EXE` to be set, but it's not"),
             Error::NoBitcoindExecutableFound =>  write!(f, "`bitcoind` executable is required, provide it with one of the following: set env var `BITCOIND_EXE` or use a feature like \"22_0\" or have `bitcoind` executable in the `PATH`"),
             Error::BothFeatureAndEnvVar => write!(f, "Called a method requiring env var `BITCOIND_EXE` or a feature to be set, but both are set"),
             Error::EarlyExit(e) => write!(f, "The bitcoind process terminated early with exit code {}", e),
             Error::BothDirsSpecified => write!(f, "tempdir and staticdir cannot be enabled at same time in configuration options"),
-            Error::RpcUserAndPasswordUsed => write!(f, "`-rpcuser` and `-rpcpassword` cannot be used, it will be deprecated soon and it's recommended to use `-rpcauth` instead which works alongside with the default cookie authentication")
+            Error::RpcUserAndPasswordUsed => write!(f, "`-rpcuser` and `-rpcpassword` cannot be used, it will be deprecated soon and it's recommended to use `-rpcauth` instead which works alongside with the default cookie authentication"),
+            Error::UnsupportedVersion { option, version } => write!(f, "`{}` is not supported by bitcoind version {}", option, version),
+            Error::ConflictingOptions { option, conflicts_with } => write!(f, "`{}` cannot be used together with `{}`", option, conflicts_with),
+            Error::UnknownSoftfork(name) => write!(f, "The node doesn't know the softfork `{}`", name),
+            Error::Timeout(timeout) => write!(f, "Condition not met within {:?}", timeout),
+            Error::StartupTimeout(timeout) => write!(f, "The bitcoind process was not ready within {:?}", timeout),
+            Error::IncompleteSignature => write!(f, "Some inputs of the transaction are not signed"),
+            Error::UnknownVersion(version) => write!(f, "Version `{}` is not supported, supported versions are {}", version, SUPPORTED_VERSIONS.join(", ")),
+            Error::VersionNotDownloaded { requested, downloaded } => write!(f, "Version `{}` has not been downloaded (downloaded version: {}), enable the feature `{}`", requested, downloaded, requested.replace('.', "_")),
+            Error::InvalidFraction(fraction) => write!(f, "{} is not a fraction in the range (0, 1]", fraction),
+            Error::UnexpectedLogError(line) => write!(f, "Unexpected error in debug.log: {}", line),
+            Error::TxAccepted(txid) => write!(f, "Transaction {} has been accepted while a rejection was expected", txid),
+            Error::RejectReasonMismatch { expected, actual } => write!(f, "Transaction rejected with `{}` while `{}` was expected", actual, expected),
+            Error::NoP2pPort => write!(f, "The node doesn't open a p2p port, use P2P::Yes"),
+            Error::UncleanExit(status) => write!(f, "The bitcoind process didn't stop cleanly: {}", status),
+            Error::CookieNotFound(path) => write!(f, "Cookie file {:?} not found, the node didn't write it yet or uses rpc_auth", path),
+            Error::ExeNotFound(exe) => write!(f, "bitcoind executable {:?} not found", exe),
+            Error::InvalidPruneTarget(prune) => write!(f, "Prune target {} MiB is below the minimum of {} MiB, use 1 for manual pruning", prune, MIN_PRUNE_TARGET_MIB),
+            Error::ReservedOption(key) => write!(f, "`-{}` is set by this crate and cannot be used in `Conf::args`, `Conf::set` or `BitcoinD::restart_with_args`", key),
+            Error::BlockRejected(reason) => write!(f, "The block has been rejected: {}", reason),
+            Error::WalletNotLoaded(name) => write!(f, "The wallet `{}` is not loaded in the node", name),
+            Error::UnknownFee(txid) => write!(f, "The fee of transaction {} is unknown, it's not in the mempool nor sent by the wallet", txid),
+            Error::TipMismatch { this, other } => write!(f, "Nodes are at different tips: this node is at {} while the other is at {}", this, other),
         }
     }
 }
@@ -148,46 +968,129 @@ impl std::error::Error for Error {
     }
 }
 
+/// Challenge of the default signet, used when [Conf::signet_challenge] is `None`
+const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
 const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
 
 const INVALID_ARGS: [&str; 2] = ["-rpcuser", "-rpcpassword"];
 
+/// Options always set by this crate, which cannot be used in [Conf::args], [Conf::set] or
+/// [BitcoinD::restart_with_args]. `connect` and `bind` are reserved too when set because of
+/// [Conf::p2p] and [Conf::p2p_bind].
+const RESERVED_OPTIONS: [&str; 4] = ["datadir", "rpcport", "port", "listen"];
+
+/// Arguments selecting the network, set according to [Conf::network]
+const NETWORK_ARGS: [&str; 5] = ["-regtest", "-signet", "-testnet", "-testnet4", "-chain"];
+
+/// Maximum time waited for a p2p connection between local nodes to be established
+const LINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum time waited for the node to exit after the `stop` RPC when dropped, before killing it
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of outputs of the transactions created by [BitcoinD::fill_mempool]
+const FILL_OUTPUTS: usize = 100;
+
+/// Upper bound of the virtual size of the transactions created by [BitcoinD::fill_mempool]
+const FILL_TX_MAX_VSIZE: u64 = 3_500;
+
+/// Value in satoshi of the confirmed output spent by every [BitcoinD::fill_mempool] transaction
+const FILL_INPUT_SAT: u64 = 100_000;
+
+/// Descriptor used by [BitcoinD::deterministic_address], the extended public key is the master key
+/// of the BIP32 test vector 1 seed `000102030405060708090a0b0c0d0e0f` with testnet encoding
+const DETERMINISTIC_DESCRIPTOR: &str = "wpkh(tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp/0/*)";
+
 /// The node configuration parameters, implements a convenient [Default] for most common use.
 ///
 /// `#[non_exhaustive]` allows adding new parameters without breaking downstream users.
 /// Users cannot instantiate the struct directly, they need to create it via the `default()` method
-/// and mutate fields according to their preference.
+/// and mutate fields according to their preference. Options added in the future become new
+/// fields, so [BitcoinD::with_conf] signature doesn't change. Being `Clone`, a configuration can
+/// be stored and used to launch several nodes.
 ///
 /// Default values:
 /// ```
 /// let mut conf = bitcoind::Conf::default();
-/// conf.args = vec!["-regtest", "-fallbackfee=0.0001"];
-/// conf.view_stdout = false;
+/// conf.args = vec![];
+/// conf.output = bitcoind::Output::Null;
+/// conf.log_tee = None;
 /// conf.p2p = bitcoind::P2P::No;
-/// conf.network = "regtest";
+/// conf.network = bitcoind::Network::Regtest;
 /// conf.tmpdir = None;
 /// conf.staticdir = None;
-/// conf.attempts = 3;
+/// conf.reusedir = None;
+/// conf.startup = bitcoind::StartupPolicy::default();
+/// conf.rpc_threads = None;
+/// conf.rpc_work_queue = None;
+/// conf.p2p_bind = None;
+/// conf.proxy = None;
+/// conf.limit_ancestor_size = None;
+/// conf.limit_descendant_size = None;
+/// conf.accept_stale_fee_estimates = false;
+/// conf.max_connections = None;
+/// conf.shrink_debug_file = None;
+/// conf.db_batch_size = None;
+/// conf.max_sig_cache_size = None;
+/// conf.par = None;
+/// conf.whitelist_relay = None;
+/// conf.whitelist_force_relay = None;
+/// conf.block_notify = None;
+/// conf.max_send_buffer_kb = None;
+/// conf.max_receive_buffer_kb = None;
+/// conf.persist_mempool_v1 = None;
+/// conf.capture_messages = false;
+/// conf.address_type = None;
+/// conf.change_type = None;
+/// conf.max_aps_fee = None;
+/// conf.dust_relay_fee = None;
+/// conf.keypool = None;
+/// conf.bootstrap_addresses = 0;
+/// conf.activate_taproot_at = None;
+/// conf.max_mempool_mb = None;
+/// conf.rpc_auth = bitcoind::RpcAuth::Cookie;
+/// conf.copy_datadir_from = None;
+/// conf.read_only = false;
+/// conf.wallet = Some("default");
+/// conf.wallet_descriptors = None;
+/// conf.signet_challenge = None;
+/// conf.zmq = false;
+/// conf.options = vec![];
+/// conf.max_time_adjustment = None;
+/// conf.rpc_proxy = None;
+/// conf.fallback_fee = Some(bitcoind::bitcoincore_rpc::bitcoin::Amount::from_sat(10_000));
+/// conf.block_filter_index = false;
+/// conf.prune = None;
+/// conf.envs = vec![];
+/// conf.keep_datadir_on_panic = false;
+/// conf.write_conf_file = false;
 /// assert_eq!(conf, bitcoind::Conf::default());
 /// ```
 ///
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Conf<'a> {
-    /// Bitcoind command line arguments containing no spaces like `vec!["-dbcache=300", "-regtest"]`
-    /// note that `port`, `rpcport`, `connect`, `datadir`, `listen`
-    /// cannot be used because they are automatically initialized.
+    /// Bitcoind command line arguments containing no spaces like `vec!["-dbcache=300"]`
+    /// note that `port`, `rpcport`, `datadir`, `listen`, `connect` with [P2P::Connect], `bind`
+    /// with [Conf::p2p_bind] and network arguments other than [Conf::network] cannot be used
+    /// because they are automatically initialized, [Error::ReservedOption] is returned.
     pub args: Vec<&'a str>,
 
-    /// if `true` bitcoind log output will not be suppressed
-    pub view_stdout: bool,
+    /// Where bitcoind stdout and stderr go, by default stdout is discarded
+    pub output: Output,
+
+    /// Write bitcoind log output both to the given file and to the stdout of this process, so
+    /// that logs can be watched live and kept as an artifact at the same time. When set
+    /// `output` is ignored.
+    pub log_tee: Option<PathBuf>,
 
     /// Allows to specify options to open p2p port or connect to the another node
     pub p2p: P2P,
 
-    /// Must match what specified in args without dashes, needed to locate the cookie file
-    /// directory with different/esoteric networks
-    pub network: &'a str,
+    /// The network the node runs on, it sets the network argument and locates the cookie file.
+    /// Other network arguments in [Conf::args] are rejected.
+    pub network: Network,
 
     /// Optionally specify a temporary or persistent working directory for the node.
     /// The following two parameters can be configured to simulate desired working directory configuration.
@@ -204,165 +1107,950 @@ pub struct Conf<'a> {
     /// Temporary directory path
     pub tmpdir: Option<PathBuf>,
 
-    /// Persistent directory path
+    /// Persistent directory path, used as is: it may be pre-seeded with chain data and it's not
+    /// deleted when [BitcoinD] is dropped, the node is stopped cleanly so that the chainstate can
+    /// be inspected or the directory reused by another node
     pub staticdir: Option<PathBuf>,
 
-    /// Try to spawn the process `attempt` time
+    /// Directory reused across sequential nodes, emptied before the node starts and after it's
+    /// dropped. It avoids creating and deleting a temporary directory for every node, which adds up
+    /// in very large suites of fast tests.
     ///
-    /// The OS is giving available ports to use, however, they aren't booked, so it could rarely
-    /// happen they are used at the time the process is spawn. When retrying other available ports
-    /// are returned reducing the probability of conflicts to negligible.
-    pub attempts: u8,
+    /// Only one node at a time can use the directory, so tests sharing it must run
+    /// single-threaded (eg. `cargo test -- --test-threads=1`). Cannot be used together with
+    /// `tmpdir` or `staticdir`.
+    pub reusedir: Option<PathBuf>,
+
+    /// Controls how long to wait for the node to be ready and how many times to retry on failures
+    pub startup: StartupPolicy,
+
+    /// Number of threads bitcoind uses to serve RPC calls (`-rpcthreads`), when `None` the
+    /// bitcoind default is used
+    pub rpc_threads: Option<u16>,
+
+    /// Maximum number of RPC calls queued waiting for a free thread (`-rpcworkqueue`), further
+    /// calls are rejected with "Work queue depth exceeded". When `None` the bitcoind default is
+    /// used (16), raise it together with [Conf::rpc_threads] when firing bursts of calls.
+    pub rpc_work_queue: Option<u32>,
+
+    /// Address the p2p listener is bound to (`-bind`) instead of an auto-assigned local port,
+    /// it's reported in [ConnectParams::p2p_socket]. Used only if [Conf::p2p] opens a p2p port.
+    pub p2p_bind: Option<SocketAddrV4>,
+
+    /// SOCKS5 proxy used for outgoing p2p connections (`-proxy`), eg. a Tor SOCKS port
+    pub proxy: Option<SocketAddrV4>,
+
+    /// Maximum size in kvB of a mempool transaction together with its in-mempool ancestors
+    /// (`-limitancestorsize`)
+    pub limit_ancestor_size: Option<u32>,
+
+    /// Maximum size in kvB of a mempool transaction together with its in-mempool descendants
+    /// (`-limitdescendantsize`)
+    pub limit_descendant_size: Option<u32>,
+
+    /// Let `estimatesmartfee` return estimates even when based on stale data
+    /// (`-acceptstalefeeestimates`), handy on regtest where blocks are mined only on demand.
+    /// Requires bitcoind 25.0 or later.
+    pub accept_stale_fee_estimates: bool,
+
+    /// Maximum number of automatic and inbound connections (`-maxconnections`).
+    ///
+    /// With `Some(0)` the node refuses any inbound connection while the ones requested with
+    /// [P2P::Connect] are still made, thus it can't be used with [P2P::Yes] or with
+    /// [P2P::Connect] accepting connections.
+    pub max_connections: Option<u32>,
+
+    /// Whether `debug.log` is shrunk at startup (`-shrinkdebugfile`), when `None` bitcoind shrinks
+    /// it unless `-debug` is used. Keeping it is useful to inspect the log of a persistent
+    /// datadir across restarts.
+    pub shrink_debug_file: Option<bool>,
+
+    /// Maximum size in bytes of the database write batches used when flushing the chainstate
+    /// (`-dbbatchsize`), together with `-dbcache` it tunes the flush behavior in IO benchmarks
+    pub db_batch_size: Option<u32>,
+
+    /// Maximum size in MiB of the signature and script verification caches (`-maxsigcachesize`)
+    pub max_sig_cache_size: Option<u32>,
+
+    /// Number of script verification threads (`-par`), `0` means automatic and negative values
+    /// leave that many cores free
+    pub par: Option<i32>,
+
+    /// Whether transactions from whitelisted peers are relayed even when not relaying
+    /// transactions (`-whitelistrelay`), the whitelist itself is given with `-whitelist` in args
+    pub whitelist_relay: Option<bool>,
+
+    /// Whether transactions from whitelisted peers are relayed even if they violate the local
+    /// relay policy (`-whitelistforcerelay`)
+    pub whitelist_force_relay: Option<bool>,
+
+    /// Command executed when the best block changes (`-blocknotify`), `%s` is replaced by the block
+    /// hash. Since the command is run by the shell, something like `echo %s >> /path/to/file` lets
+    /// a test watch the notified hashes in a file.
+    pub block_notify: Option<&'a str>,
+
+    /// Maximum per-connection send buffer in KB (`-maxsendbuffer`)
+    pub max_send_buffer_kb: Option<u32>,
+
+    /// Maximum per-connection receive buffer in KB (`-maxreceivebuffer`)
+    pub max_receive_buffer_kb: Option<u32>,
+
+    /// Select the format of `mempool.dat` written on shutdown (`-persistmempoolv1`), `Some(true)`
+    /// writes the legacy v1 format readable by older versions, `Some(false)` the obfuscated v2
+    /// format. Useful to check a mempool dumped by a version can be loaded by another.
+    /// Requires bitcoind 27.0 or later.
+    pub persist_mempool_v1: Option<bool>,
+
+    /// Dump every p2p message sent and received to the `message_capture` directory of the node
+    /// (`-capturemessages`), read them with [BitcoinD::captured_messages].
+    /// Requires bitcoind 22.0 or later.
+    pub capture_messages: bool,
+
+    /// Type of the addresses returned by `getnewaddress` (`-addresstype`)
+    pub address_type: Option<AddressType>,
+
+    /// Type of the change addresses (`-changetype`), when `None` bitcoind uses the type of the
+    /// payment addresses
+    pub change_type: Option<AddressType>,
+
+    /// Maximum additional fee the wallet pays to avoid partial spends (`-maxapsfee`).
+    /// Requires bitcoind 0.21.0 or later.
+    pub max_aps_fee: Option<MaxApsFee>,
+
+    /// Fee rate per kvB used to define dust outputs (`-dustrelayfee`), an output is dust when
+    /// its value is less than the fee to create and spend it at this rate
+    pub dust_relay_fee: Option<Amount>,
+
+    /// Number of keys the wallet keeps pre-generated in its keypool (`-keypool`)
+    pub keypool: Option<u32>,
+
+    /// Number of addresses generated right after the wallet is created, which are then handed
+    /// out by [BitcoinD::next_address]. It moves the keypool work in the setup phase, so that
+    /// tests needing many fresh addresses don't pay for keypool refills in their body.
+    pub bootstrap_addresses: u32,
+
+    /// Activate taproot at the given height instead of from genesis, regtest only. Taproot is
+    /// activated through a version bits deployment (`-vbparams`, `-testactivationheight` doesn't
+    /// accept taproot) which requires signalling periods of 144 blocks, so taproot is active at
+    /// the first period boundary after at least 3 periods and not before the given height.
+    /// Requires bitcoind 0.21.1 or later, the first supporting a minimum activation height.
+    pub activate_taproot_at: Option<u32>,
+
+    /// Maximum size of the mempool in MB (`-maxmempool`), bitcoind requires at least 5. A small
+    /// mempool makes [BitcoinD::fill_mempool] fast.
+    pub max_mempool_mb: Option<u32>,
+
+    /// Credentials of [BitcoinD::client], useful when other tools can't read the cookie file (eg.
+    /// because of permissions in containers) and need static credentials, see
+    /// [ConnectParams::auth]. Defaults to [RpcAuth::Cookie].
+    pub rpc_auth: RpcAuth,
+
+    /// Data directory copied in the working directory of the node before it starts, eg. a
+    /// snapshot of a chain expensive to build. The copy is used, so the snapshot is never modified
+    /// by the node and can be reused across tests.
+    ///
+    /// Either the data directory or its network subdirectory (eg. `regtest/`, containing
+    /// `blocks/`) can be given. Lock, pid and cookie files of a node left running on the snapshot
+    /// are not copied.
+    pub copy_datadir_from: Option<PathBuf>,
+
+    /// Run the node without a wallet (`-disablewallet`): no wallet is created regardless of
+    /// [Conf::wallet] and [BitcoinD::client] connects to the node endpoint. Meant for tests only
+    /// reading chain data, together with [Conf::copy_datadir_from]. Cannot be used together with
+    /// [Conf::bootstrap_addresses].
+    pub read_only: bool,
+
+    /// Wallet created (or loaded if it exists) at startup, which [BitcoinD::client] connects to.
+    /// When `None` no wallet is created and [BitcoinD::client] connects to the node endpoint, which
+    /// is required by bitcoind built without wallet support. Cannot be `None` together with
+    /// [Conf::bootstrap_addresses].
+    pub wallet: Option<&'a str>,
+
+    /// Whether [Conf::wallet] and the wallets created with [BitcoinD::create_wallet] are
+    /// descriptor wallets (`true`) or legacy wallets (`false`), when `None` the bitcoind default
+    /// is used. Requires bitcoind 0.21 or later, recent versions support only descriptor wallets.
+    pub wallet_descriptors: Option<bool>,
+
+    /// Hex script blocks must satisfy on a custom signet (`-signetchallenge`), requires `signet`
+    /// as [Conf::network].
+    ///
+    /// The network magic, which p2p messages start with, is derived from the challenge, so nodes
+    /// with different challenges can't talk to each other. Regtest nodes all share the same magic
+    /// and can connect to any regtest node, for fully isolated p2p tests use a challenge from
+    /// [unique_signet_challenge] on every group of nodes, see [Conf::network_magic].
+    pub signet_challenge: Option<String>,
+
+    /// Publish raw blocks and raw transactions with ZMQ on non-used local ports, the endpoints
+    /// (to be prefixed with `tcp://`) are reported in [ConnectParams::zmq_rawblock] and
+    /// [ConnectParams::zmq_rawtx]. Requires bitcoind built with ZMQ support.
+    pub zmq: bool,
+
+    /// Options without a typed field given to bitcoind as `-<key>=<value>`, see [Conf::set]
+    pub options: Vec<(String, String)>,
+
+    /// Maximum adjustment of the node clock based on the time reported by peers
+    /// (`-maxtimeadjustment`), with a zero duration peers never shift the clock, which together
+    /// with the `setmocktime` RPC makes time-based tests deterministic. Precision is seconds.
+    pub max_time_adjustment: Option<Duration>,
+
+    /// HTTP proxy (`host:port`) the RPC calls of [BitcoinD::client] and of the other clients
+    /// created by [BitcoinD] go through, for environments where the node is not directly
+    /// reachable. When `None` the node is connected directly.
+    pub rpc_proxy: Option<String>,
+
+    /// Fee rate per kvB used by the wallet when there are not enough data to estimate fees
+    /// (`-fallbackfee`), which is always the case in regtest, so that wallet transactions can be
+    /// sent without specifying a fee rate. Defaults to 0.0001 BTC/kvB, it's not given to nodes
+    /// without a wallet since bitcoind built without wallet support rejects it.
+    pub fallback_fee: Option<Amount>,
+
+    /// Build the BIP158 compact block filters index and serve the filters to peers as of BIP157
+    /// (`-blockfilterindex=1 -peerblockfilters=1`), query them with [BitcoinD::get_block_filter].
+    /// Requires bitcoind 0.19.0 or later.
+    pub block_filter_index: bool,
+
+    /// Prune the block files (`-prune`), `Some(1)` allows only manual pruning with the
+    /// `pruneblockchain` RPC while greater values are the target size in MiB of the block files,
+    /// automatically pruned. The minimum target accepted by bitcoind is [MIN_PRUNE_TARGET_MIB].
+    ///
+    /// Conflicts with `-txindex` and with [Conf::block_filter_index] before bitcoind 0.21.0.
+    pub prune: Option<u32>,
+
+    /// Environment variables given to the node process, in addition to the ones inherited from
+    /// this process which are overridden if they have the same key. Useful when the executable is
+    /// a wrapper script configured through the environment.
+    pub envs: Vec<(OsString, OsString)>,
+
+    /// Keep the temporary or reused data directory if the node is dropped while the thread is
+    /// panicking, eg. because of a failed assertion, printing its path on stderr so that
+    /// chainstate and logs can be inspected. The `BITCOIND_KEEP_ON_PANIC` env var enables it too.
+    pub keep_datadir_on_panic: bool,
+
+    /// Write the options to a `bitcoin.conf` in the data directory and launch the node with just
+    /// `-datadir` and `-conf`, instead of passing every option on the command line. The assigned
+    /// rpc and p2p ports are written to the file too, which is rewritten at every (re)start,
+    /// replacing any existing one, eg. copied with [Conf::copy_datadir_from]. Extra arguments
+    /// given to [BitcoinD::restart_with_args] stay on the command line.
+    pub write_conf_file: bool,
 }
 
+/// Minimum target size in MiB of the block files of a pruned node, see [Conf::prune]
+pub const MIN_PRUNE_TARGET_MIB: u32 = 550;
+
 impl Default for Conf<'_> {
     fn default() -> Self {
         Conf {
-            args: vec!["-regtest", "-fallbackfee=0.0001"],
-            view_stdout: false,
+            args: vec![],
+            output: Output::Null,
+            log_tee: None,
             p2p: P2P::No,
-            network: "regtest",
+            network: Network::Regtest,
             tmpdir: None,
             staticdir: None,
-            attempts: 3,
+            reusedir: None,
+            startup: StartupPolicy::default(),
+            rpc_threads: None,
+            rpc_work_queue: None,
+            p2p_bind: None,
+            proxy: None,
+            limit_ancestor_size: None,
+            limit_descendant_size: None,
+            accept_stale_fee_estimates: false,
+            max_connections: None,
+            shrink_debug_file: None,
+            db_batch_size: None,
+            max_sig_cache_size: None,
+            par: None,
+            whitelist_relay: None,
+            whitelist_force_relay: None,
+            block_notify: None,
+            max_send_buffer_kb: None,
+            max_receive_buffer_kb: None,
+            persist_mempool_v1: None,
+            capture_messages: false,
+            address_type: None,
+            change_type: None,
+            max_aps_fee: None,
+            dust_relay_fee: None,
+            keypool: None,
+            bootstrap_addresses: 0,
+            activate_taproot_at: None,
+            max_mempool_mb: None,
+            rpc_auth: RpcAuth::Cookie,
+            copy_datadir_from: None,
+            read_only: false,
+            wallet: Some("default"),
+            wallet_descriptors: None,
+            signet_challenge: None,
+            zmq: false,
+            options: vec![],
+            max_time_adjustment: None,
+            rpc_proxy: None,
+            fallback_fee: Some(Amount::from_sat(10_000)),
+            block_filter_index: false,
+            prune: None,
+            envs: vec![],
+            keep_datadir_on_panic: false,
+            write_conf_file: false,
         }
     }
 }
 
-impl BitcoinD {
-    /// Launch the bitcoind process from the given `exe` executable with default args.
-    ///
-    /// Waits for the node to be ready to accept connections before returning
-    pub fn new<S: AsRef<OsStr>>(exe: S) -> anyhow::Result<BitcoinD> {
-        BitcoinD::with_conf(exe, &Conf::default())
-    }
+/// Controls the startup of the node, used in [Conf::startup].
+///
+/// Default values:
+/// ```
+/// use std::time::Duration;
+/// let mut startup = bitcoind::StartupPolicy::default();
+/// startup.timeout = Some(Duration::from_secs(10));
+/// startup.poll_interval = Duration::from_millis(50);
+/// startup.max_poll_interval = Duration::from_millis(500);
+/// startup.max_port_retries = 3;
+/// startup.wait_for_full_sync = false;
+/// assert_eq!(startup, bitcoind::StartupPolicy::default());
+/// ```
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StartupPolicy {
+    /// Maximum time to wait for the node to be ready to accept RPC calls before considering the
+    /// launch failed with [Error::StartupTimeout], when `None` waits indefinitely
+    pub timeout: Option<Duration>,
+
+    /// Interval between the first checks of the node readiness, doubled at every check up to
+    /// `max_poll_interval` so that fast nodes are noticed early and slow ones aren't hammered.
+    /// When greater than `max_poll_interval` it's used for every check.
+    pub poll_interval: Duration,
+
+    /// Maximum interval between checks of the node readiness
+    pub max_poll_interval: Duration,
+
+    /// Number of times the launch is retried with newly assigned ports after a recoverable failure,
+    /// meaning the process exited early or it wasn't ready within `timeout`.
+    ///
+    /// The OS is giving available ports to use, however, they aren't booked, so it could rarely
+    /// happen they are used at the time the process is spawn. When retrying other available ports
+    /// are returned reducing the probability of conflicts to negligible.
+    pub max_port_retries: u8,
+
+    /// Besides accepting RPC calls, wait until the node has validated all the headers it knows
+    /// (`blocks` equal to `headers` and `verificationprogress` at 1 in `getblockchaininfo`), eg.
+    /// when launched from a copied datadir or connected to peers at startup.
+    ///
+    /// `initialblockdownload` is not checked since a fresh regtest node stays in IBD until a block
+    /// is mined.
+    pub wait_for_full_sync: bool,
+}
+
+impl StartupPolicy {
+    /// Returns the interval to wait after having waited `interval` without the node being ready
+    fn next_poll_interval(&self, interval: Duration) -> Duration {
+        // never shrinks, when `poll_interval` is greater than `max_poll_interval`
+        std::cmp::max(
+            interval,
+            std::cmp::min(interval * 2, self.max_poll_interval),
+        )
+    }
+}
+
+impl Default for StartupPolicy {
+    fn default() -> Self {
+        StartupPolicy {
+            timeout: Some(Duration::from_secs(10)),
+            poll_interval: Duration::from_millis(50),
+            max_poll_interval: Duration::from_millis(500),
+            max_port_retries: 3,
+            wait_for_full_sync: false,
+        }
+    }
+}
+
+impl Conf<'_> {
+    /// Returns the arguments given by default to the node, depending on whether it has a wallet
+    fn default_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        let wallet_enabled = self.wallet.is_some() && !self.read_only;
+        if let (Some(fee), true) = (self.fallback_fee, wallet_enabled) {
+            args.push(format!(
+                "-fallbackfee={}",
+                fee.to_string_in(Denomination::Bitcoin)
+            ));
+        }
+        args
+    }
+
+    /// Adds the option `-<key>=<value>` for bitcoind options without a typed field in [Conf].
+    /// Options set by this crate (eg. `datadir`, `rpcport`, `port`, `connect`, `listen`) are
+    /// rejected with [Error::ReservedOption] when the node is launched.
+    ///
+    /// ```
+    /// let mut conf = bitcoind::Conf::default();
+    /// conf.set("maxorphantx", 10).set("blockfilterindex", 1);
+    /// ```
+    pub fn set<V: ToString>(&mut self, key: &str, value: V) -> &mut Self {
+        let key = key.trim_start_matches('-').to_string();
+        self.options.push((key, value.to_string()));
+        self
+    }
+
+    /// Returns the magic bytes starting the p2p messages of the node, derived from
+    /// [Conf::signet_challenge] on signet. `None` for an invalid challenge.
+    pub fn network_magic(&self) -> Option<[u8; 4]> {
+        let magic = match self.network {
+            Network::Regtest => [0xfa, 0xbf, 0xb5, 0xda],
+            Network::Testnet => [0x0b, 0x11, 0x09, 0x07],
+            Network::Testnet4 => [0x1c, 0x16, 0x3f, 0x28],
+            Network::Signet => {
+                let challenge = self
+                    .signet_challenge
+                    .as_deref()
+                    .unwrap_or(DEFAULT_SIGNET_CHALLENGE);
+                let challenge = Vec::<u8>::from_hex(challenge).ok()?;
+                let hash = sha256d::Hash::hash(&serialize(&challenge));
+                [hash[0], hash[1], hash[2], hash[3]]
+            }
+        };
+        Some(magic)
+    }
+
+    /// Returns the arguments derived from the typed options of this configuration
+    fn option_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(rpc_threads) = self.rpc_threads {
+            args.push(format!("-rpcthreads={}", rpc_threads));
+        }
+        if let Some(rpc_work_queue) = self.rpc_work_queue {
+            args.push(format!("-rpcworkqueue={}", rpc_work_queue));
+        }
+        if let Some(proxy) = self.proxy {
+            args.push(format!("-proxy={}", proxy));
+        }
+        if let Some(size) = self.limit_ancestor_size {
+            args.push(format!("-limitancestorsize={}", size));
+        }
+        if let Some(size) = self.limit_descendant_size {
+            args.push(format!("-limitdescendantsize={}", size));
+        }
+        if self.accept_stale_fee_estimates {
+            args.push("-acceptstalefeeestimates=1".to_string());
+        }
+        if let Some(max_connections) = self.max_connections {
+            args.push(format!("-maxconnections={}", max_connections));
+        }
+        if let Some(shrink) = self.shrink_debug_file {
+            args.push(format!("-shrinkdebugfile={}", shrink as u8));
+        }
+        if let Some(size) = self.db_batch_size {
+            args.push(format!("-dbbatchsize={}", size));
+        }
+        if let Some(size) = self.max_sig_cache_size {
+            args.push(format!("-maxsigcachesize={}", size));
+        }
+        if let Some(par) = self.par {
+            args.push(format!("-par={}", par));
+        }
+        if let Some(relay) = self.whitelist_relay {
+            args.push(format!("-whitelistrelay={}", relay as u8));
+        }
+        if let Some(force_relay) = self.whitelist_force_relay {
+            args.push(format!("-whitelistforcerelay={}", force_relay as u8));
+        }
+        if let Some(command) = self.block_notify {
+            args.push(format!("-blocknotify={}", command));
+        }
+        if let Some(size) = self.max_send_buffer_kb {
+            args.push(format!("-maxsendbuffer={}", size));
+        }
+        if let Some(size) = self.max_receive_buffer_kb {
+            args.push(format!("-maxreceivebuffer={}", size));
+        }
+        if let Some(v1) = self.persist_mempool_v1 {
+            args.push(format!("-persistmempoolv1={}", v1 as u8));
+        }
+        if self.capture_messages {
+            args.push("-capturemessages=1".to_string());
+        }
+        if let Some(address_type) = self.address_type {
+            args.push(format!("-addresstype={}", address_type.as_str()));
+        }
+        if let Some(change_type) = self.change_type {
+            args.push(format!("-changetype={}", change_type.as_str()));
+        }
+        if let Some(max_aps_fee) = self.max_aps_fee {
+            args.push(format!("-maxapsfee={}", max_aps_fee.arg_value()));
+        }
+        if let Some(fee) = self.dust_relay_fee {
+            args.push(format!(
+                "-dustrelayfee={}",
+                fee.to_string_in(Denomination::Bitcoin)
+            ));
+        }
+        if let Some(size) = self.keypool {
+            args.push(format!("-keypool={}", size));
+        }
+        if let Some(size) = self.max_mempool_mb {
+            args.push(format!("-maxmempool={}", size));
+        }
+        if self.read_only {
+            args.push("-disablewallet=1".to_string());
+        }
+        if let Some(challenge) = &self.signet_challenge {
+            args.push(format!("-signetchallenge={}", challenge));
+        }
+        if let Some(adjustment) = self.max_time_adjustment {
+            args.push(format!("-maxtimeadjustment={}", adjustment.as_secs()));
+        }
+        if self.block_filter_index {
+            args.push("-blockfilterindex=1".to_string());
+            args.push("-peerblockfilters=1".to_string());
+        }
+        if let Some(prune) = self.prune {
+            args.push(format!("-prune={}", prune));
+        }
+        for (key, value) in &self.options {
+            args.push(format!("-{}={}", key, value));
+        }
+        args
+    }
+
+    /// Options reserved in addition to [RESERVED_OPTIONS] because they are set according to this
+    /// configuration
+    fn reserved_options(&self) -> Vec<&'static str> {
+        let mut reserved = vec![];
+        match self.p2p {
+            P2P::Connect(_, _) | P2P::ConnectMany(_, _) => reserved.push("connect"),
+            P2P::No | P2P::Yes => (),
+        }
+        if self.p2p_bind.is_some() {
+            reserved.push("bind");
+        }
+        reserved
+    }
+
+    /// Returns an error if options which cannot be used together are set
+    fn validate(&self) -> anyhow::Result<()> {
+        let reserved = self.reserved_options();
+        check_reserved(self.args.iter().cloned(), &reserved, self.network)?;
+        let options: Vec<_> = self
+            .options
+            .iter()
+            .map(|(key, value)| format!("-{}={}", key, value))
+            .collect();
+        check_reserved(options.iter().map(String::as_str), &reserved, self.network)?;
+        for (key, _) in &self.options {
+            if INVALID_ARGS.contains(&format!("-{}", key).as_str()) {
+                return Err(Error::RpcUserAndPasswordUsed.into());
+            }
+        }
+        if let Some(prune) = self.prune {
+            if prune > 1 && prune < MIN_PRUNE_TARGET_MIB {
+                return Err(Error::InvalidPruneTarget(prune).into());
+            }
+            let txindex = self
+                .args
+                .iter()
+                .any(|a| *a == "-txindex" || *a == "-txindex=1")
+                || self.options.iter().any(|(k, v)| k == "txindex" && v != "0");
+            if txindex {
+                return Err(Error::ConflictingOptions {
+                    option: "prune",
+                    conflicts_with: "-txindex",
+                }
+                .into());
+            }
+        }
+        if self.signet_challenge.is_some() && self.network != Network::Signet {
+            return Err(Error::ConflictingOptions {
+                option: "signet_challenge",
+                conflicts_with: "networks other than signet",
+            }
+            .into());
+        }
+        if self.bootstrap_addresses > 0 {
+            let conflicts_with = if self.read_only {
+                Some("read_only")
+            } else if self.wallet.is_none() {
+                Some("wallet: None")
+            } else {
+                None
+            };
+            if let Some(conflicts_with) = conflicts_with {
+                return Err(Error::ConflictingOptions {
+                    option: "bootstrap_addresses",
+                    conflicts_with,
+                }
+                .into());
+            }
+        }
+        if self.activate_taproot_at.is_some() && self.network != Network::Regtest {
+            return Err(Error::ConflictingOptions {
+                option: "activate_taproot_at",
+                conflicts_with: "networks other than regtest",
+            }
+            .into());
+        }
+        if self.reusedir.is_some() {
+            let conflicts_with = if self.tmpdir.is_some() {
+                Some("tmpdir")
+            } else if self.staticdir.is_some() {
+                Some("staticdir")
+            } else {
+                None
+            };
+            if let Some(conflicts_with) = conflicts_with {
+                return Err(Error::ConflictingOptions {
+                    option: "reusedir",
+                    conflicts_with,
+                }
+                .into());
+            }
+        }
+        if self.max_connections == Some(0) {
+            match self.p2p {
+                P2P::Yes | P2P::Connect(_, true) | P2P::ConnectMany(_, true) => {
+                    return Err(Error::ConflictingOptions {
+                        option: "-maxconnections=0",
+                        conflicts_with: "-listen",
+                    }
+                    .into())
+                }
+                P2P::No | P2P::Connect(_, false) | P2P::ConnectMany(_, false) => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the arguments depending on the version of the given `exe` or an error if options
+    /// not supported by it are used, the version of the executable is checked only if some
+    /// version dependent option is used
+    fn version_args(&self, exe: &OsStr) -> anyhow::Result<Vec<String>> {
+        let mut required = vec![];
+        if self.accept_stale_fee_estimates {
+            required.push(("-acceptstalefeeestimates", 250_000));
+        }
+        if self.persist_mempool_v1.is_some() {
+            required.push(("-persistmempoolv1", 270_000));
+        }
+        if self.capture_messages {
+            required.push(("-capturemessages", 220_000));
+        }
+        if self.block_filter_index {
+            required.push(("-blockfilterindex", 190_000));
+            if self.prune.is_some() {
+                required.push(("-blockfilterindex with -prune", 210_000));
+            }
+        }
+        for &(option, address_type) in &[
+            ("-addresstype", self.address_type),
+            ("-changetype", self.change_type),
+        ] {
+            match address_type {
+                Some(t) if t.min_version() > 0 => required.push((option, t.min_version())),
+                _ => (),
+            }
+        }
+        if self.max_aps_fee.is_some() {
+            required.push(("-maxapsfee", 210_000));
+        }
+        if self.activate_taproot_at.is_some() {
+            required.push(("activate_taproot_at", 210_100));
+        }
+        if self.network == Network::Testnet4 {
+            required.push(("-testnet4", 280_000));
+        }
+        if self.wallet_descriptors.is_some() && self.wallet.is_some() && !self.read_only {
+            required.push(("wallet_descriptors", 210_000));
+        }
+        if required.is_empty() {
+            return Ok(vec![]);
+        }
+        let version = exe_version(exe)?;
+        for (option, min_version) in required {
+            if version < min_version {
+                return Err(Error::UnsupportedVersion { option, version }.into());
+            }
+        }
+        let mut args = vec![];
+        if let Some(height) = self.activate_taproot_at {
+            args.push(taproot_activation_arg(height));
+        }
+        Ok(args)
+    }
+}
+
+impl BitcoinD {
+    /// Launch the bitcoind process from the given `exe` executable with default args.
+    ///
+    /// Waits for the node to be ready to accept connections before returning
+    pub fn new<S: AsRef<OsStr>>(exe: S) -> anyhow::Result<BitcoinD> {
+        BitcoinD::with_conf(exe, &Conf::default())
+    }
 
     /// Launch the bitcoind process from the given `exe` executable with given [Conf] param
     pub fn with_conf<S: AsRef<OsStr>>(exe: S, conf: &Conf) -> anyhow::Result<BitcoinD> {
+        let (mut starting, work_dir) = BitcoinD::spawn_node(exe.as_ref(), conf)?;
+        // wait bitcoind is ready, use the configured wallet
+        match starting.wait_ready()? {
+            Some(client) => starting.finish(client, work_dir, conf),
+            None => Self::retry_with_conf(&starting.launch.exe, conf),
+        }
+    }
+
+    /// Launch the node process, without waiting for it to be ready
+    fn spawn_node(exe: &OsStr, conf: &Conf) -> anyhow::Result<(Starting, DataDir)> {
+        conf.validate()?;
+        let exe = resolve_exe(exe)?;
         let tmpdir = conf
             .tmpdir
             .clone()
             .or_else(|| env::var("TEMPDIR_ROOT").map(PathBuf::from).ok());
-        let work_dir = match (&tmpdir, &conf.staticdir) {
-            (Some(_), Some(_)) => return Err(Error::BothDirsSpecified.into()),
-            (Some(tmpdir), None) => DataDir::Temporary(TempDir::new_in(tmpdir)?),
-            (None, Some(workdir)) => {
-                fs::create_dir_all(workdir)?;
-                DataDir::Persistent(workdir.to_owned())
+        let work_dir = if let Some(reusedir) = &conf.reusedir {
+            clear_dir(reusedir)?;
+            DataDir::Reused(reusedir.to_owned())
+        } else {
+            match (&tmpdir, &conf.staticdir) {
+                (Some(_), Some(_)) => return Err(Error::BothDirsSpecified.into()),
+                (Some(tmpdir), None) => DataDir::Temporary(TempDir::new_in(tmpdir)?),
+                (None, Some(workdir)) => {
+                    fs::create_dir_all(workdir)?;
+                    DataDir::Persistent(workdir.to_owned())
+                }
+                (None, None) => DataDir::Temporary(TempDir::new()?),
             }
-            (None, None) => DataDir::Temporary(TempDir::new()?),
         };
 
         let work_dir_path = work_dir.path();
         debug!("work_dir: {:?}", work_dir_path);
-        let cookie_file = work_dir_path.join(conf.network).join(".cookie");
-        let rpc_port = get_available_port()?;
-        let rpc_socket = SocketAddrV4::new(LOCAL_IP, rpc_port);
-        let rpc_url = format!("http://{}", rpc_socket);
-        let (p2p_args, p2p_socket) = match conf.p2p {
+        if let Some(snapshot) = &conf.copy_datadir_from {
+            let network_dir = work_dir_path.join(conf.network.dir());
+            if snapshot.join("blocks").is_dir() {
+                copy_dir(snapshot, &network_dir)?;
+            } else {
+                copy_dir(snapshot, &work_dir_path)?;
+            }
+        }
+        let cookie_file = work_dir_path.join(conf.network.dir()).join(".cookie");
+        // ports are kept reserved until the node is spawned
+        let mut reserved = vec![];
+        let rpc_socket = reserve_socket(&mut reserved)?;
+        let rpc_port = rpc_socket.port();
+        let (p2p_args, p2p_socket) = match &conf.p2p {
             P2P::No => (vec!["-listen=0".to_string()], None),
             P2P::Yes => {
-                let p2p_port = get_available_port()?;
-                let p2p_socket = SocketAddrV4::new(LOCAL_IP, p2p_port);
-                let p2p_arg = format!("-port={}", p2p_port);
-                let args = vec![p2p_arg];
+                let (p2p_socket, args) = p2p_listen_args(conf.p2p_bind, &mut reserved)?;
                 (args, Some(p2p_socket))
             }
             P2P::Connect(other_node_url, listen) => {
-                let p2p_port = get_available_port()?;
-                let p2p_socket = SocketAddrV4::new(LOCAL_IP, p2p_port);
-                let p2p_arg = format!("-port={}", p2p_port);
-                let connect = format!("-connect={}", other_node_url);
-                let mut args = vec![p2p_arg, connect];
-                if listen {
+                let (p2p_socket, mut args) = p2p_listen_args(conf.p2p_bind, &mut reserved)?;
+                args.push(format!("-connect={}", other_node_url));
+                if *listen {
+                    args.push("-listen=1".to_string())
+                }
+                (args, Some(p2p_socket))
+            }
+            P2P::ConnectMany(other_node_urls, listen) => {
+                let (p2p_socket, mut args) = p2p_listen_args(conf.p2p_bind, &mut reserved)?;
+                for url in other_node_urls {
+                    args.push(format!("-connect={}", url));
+                }
+                if *listen {
                     args.push("-listen=1".to_string())
                 }
                 (args, Some(p2p_socket))
             }
         };
-        let stdout = if conf.view_stdout {
-            Stdio::inherit()
+        let (zmq_args, zmq_rawblock, zmq_rawtx) = if conf.zmq {
+            let rawblock = reserve_socket(&mut reserved)?;
+            let rawtx = reserve_socket(&mut reserved)?;
+            let args = vec![
+                format!("-zmqpubrawblock=tcp://{}", rawblock),
+                format!("-zmqpubrawtx=tcp://{}", rawtx),
+            ];
+            (args, Some(rawblock), Some(rawtx))
         } else {
-            Stdio::null()
+            (vec![], None, None)
+        };
+        let auth_args = match &conf.rpc_auth {
+            RpcAuth::Cookie => vec![],
+            RpcAuth::UserPass(user, password) => {
+                let salt = random_salt();
+                vec![rpcauth_arg(user, password, &salt)]
+            }
         };
-
         let datadir_arg = format!("-datadir={}", work_dir_path.display());
-        let rpc_arg = format!("-rpcport={}", rpc_port);
-        let default_args = [&datadir_arg, &rpc_arg];
+        let network_arg = conf.network.arg().to_string();
         let conf_args = validate_args(conf.args.clone())?;
+        let version_args = conf.version_args(&exe)?;
+        let default_args = conf.default_args();
+        let option_args = conf.option_args();
 
         debug!(
-            "launching {:?} with args: {:?} {:?} AND custom args: {:?}",
-            exe.as_ref(),
-            default_args,
+            "launching {:?} with args: {:?} {:?} {:?} {:?} {:?} {:?} AND custom args: {:?}",
+            exe,
+            datadir_arg,
+            network_arg,
             p2p_args,
+            default_args,
+            option_args,
+            version_args,
             conf_args
         );
 
-        let mut process = Command::new(exe.as_ref())
-            .args(&default_args)
-            .args(&p2p_args)
-            .args(&conf_args)
-            .stdout(stdout)
-            .spawn()
-            .with_context(|| format!("Error while executing {:?}", exe.as_ref()))?;
-
-        let node_url_default = format!("{}/wallet/default", rpc_url);
-        let mut i = 0;
-        // wait bitcoind is ready, use default wallet
-        let client = loop {
-            if let Some(status) = process.try_wait()? {
-                if conf.attempts > 0 {
-                    warn!("early exit with: {:?}. Trying to launch again ({} attempts remaining), maybe some other process used our available port", status, conf.attempts);
-                    let mut conf = conf.clone();
-                    conf.attempts -= 1;
-                    return Self::with_conf(exe, &conf)
-                        .with_context(|| format!("Remaining attempts {}", conf.attempts));
-                } else {
-                    error!("early exit with: {:?}", status);
-                    return Err(Error::EarlyExit(status).into());
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
-            assert!(process.stderr.is_none());
-            let client_result = Client::new(&rpc_url, Auth::CookieFile(cookie_file.clone()));
-
-            if let Ok(client_base) = client_result {
-                // RpcApi has get_blockchain_info method, however being generic with `Value` allows
-                // to be compatible with different version, in the end we are only interested if
-                // the call is succesfull not in the returned value.
-                if client_base.call::<Value>("getblockchaininfo", &[]).is_ok() {
-                    // Try creating new wallet, if fails due to already existing wallet file
-                    // try loading the same. Return if still errors.
-                    if client_base
-                        .create_wallet("default", None, None, None, None)
-                        .is_err()
-                    {
-                        client_base.load_wallet("default")?;
-                    }
-                    break Client::new(&node_url_default, Auth::CookieFile(cookie_file.clone()))?;
-                }
-            }
+        let launch = Launch {
+            exe: exe.clone(),
+            args: vec![datadir_arg, network_arg]
+                .into_iter()
+                .chain(p2p_args)
+                .chain(zmq_args)
+                .chain(auth_args)
+                .chain(default_args)
+                .chain(option_args)
+                .chain(version_args)
+                .chain(conf_args.iter().map(|arg| arg.to_string()))
+                .collect(),
+            output: conf.output,
+            output_file: work_dir_path.join(OUTPUT_FILE),
+            log_tee: conf.log_tee.clone(),
+            startup: conf.startup.clone(),
+            wallet: conf
+                .wallet
+                .filter(|_| !conf.read_only)
+                .map(|wallet| wallet.to_string()),
+            descriptors: conf.wallet_descriptors,
+            envs: conf.envs.clone(),
+            conf_file: Some(work_dir_path.join("bitcoin.conf")).filter(|_| conf.write_conf_file),
+            network: conf.network,
+            reserved: conf.reserved_options(),
+        };
+        drop(reserved);
+        let process = launch.spawn(rpc_port, false, &[])?;
 
-            debug!(
-                "bitcoin client for process {} not ready ({})",
-                process.id(),
-                i
-            );
+        let params = ConnectParams {
+            cookie_file,
+            rpc_socket,
+            p2p_socket,
+            rpc_auth: conf.rpc_auth.clone(),
+            zmq_rawblock,
+            zmq_rawtx,
+            rpc_proxy: conf.rpc_proxy.clone(),
+        };
+        Ok((Starting::new(process, launch, params), work_dir))
+    }
+
+    /// Launch `n` nodes from the given `exe` executable in a line topology, each node connected to
+    /// the previous one, waiting for every link to be established before returning
+    pub fn line<S: AsRef<OsStr>>(exe: S, n: usize) -> anyhow::Result<Vec<BitcoinD>> {
+        let mut nodes: Vec<BitcoinD> = Vec::with_capacity(n);
+        for _ in 0..n {
+            let p2p = match nodes.last() {
+                Some(previous) => previous.p2p_connect(true).expect("p2p enabled"),
+                None => P2P::Yes,
+            };
+            let conf = Conf {
+                p2p,
+                ..Default::default()
+            };
+            let node = BitcoinD::with_conf(exe.as_ref(), &conf)?;
+            wait_for(LINK_TIMEOUT, || {
+                Ok(
+                    if nodes.is_empty() || node.client.get_connection_count()? > 0 {
+                        Some(())
+                    } else {
+                        None
+                    },
+                )
+            })
+            .context("Node not connected to the previous one of the line")?;
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
 
-            i += 1;
+    /// Launch `n` nodes from the given `exe` executable in a full mesh topology, each node
+    /// connected to every other one with [BitcoinD::connect_mesh]
+    pub fn mesh<S: AsRef<OsStr>>(exe: S, n: usize) -> anyhow::Result<Vec<BitcoinD>> {
+        let conf = Conf {
+            p2p: P2P::Yes,
+            ..Default::default()
         };
+        let nodes = (0..n)
+            .map(|_| BitcoinD::with_conf(exe.as_ref(), &conf))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        BitcoinD::connect_mesh(&nodes.iter().collect::<Vec<_>>())?;
+        Ok(nodes)
+    }
 
-        Ok(BitcoinD {
-            process,
-            client,
-            work_dir,
-            params: ConnectParams {
-                cookie_file,
-                rpc_socket,
-                p2p_socket,
-            },
+    /// Connect every given node to every other one with [BitcoinD::connect_to], the nodes must
+    /// open their p2p port
+    pub fn connect_mesh(nodes: &[&BitcoinD]) -> anyhow::Result<()> {
+        for (i, node) in nodes.iter().enumerate() {
+            for other in &nodes[i + 1..] {
+                node.connect_to(other)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Launch two nodes opening their p2p port but not connected to each other, mine `blocks_a`
+    /// blocks on the first and `blocks_b` on the second so that they are on competing chains.
+    /// Connect them with [BitcoinD::connect_to] to trigger the reorg of the node on the shorter
+    /// chain.
+    pub fn partitioned_pair<S: AsRef<OsStr>>(
+        exe: S,
+        blocks_a: u64,
+        blocks_b: u64,
+    ) -> anyhow::Result<(BitcoinD, BitcoinD)> {
+        let conf = Conf {
+            p2p: P2P::Yes,
+            ..Default::default()
+        };
+        let a = BitcoinD::with_conf(exe.as_ref(), &conf)?;
+        let b = BitcoinD::with_conf(exe.as_ref(), &conf)?;
+        for (node, blocks) in &[(&a, blocks_a), (&b, blocks_b)] {
+            let address = node.client.get_new_address(None, None)?;
+            node.client.generate_to_address(*blocks, &address)?;
+        }
+        Ok((a, b))
+    }
+
+    /// Add the p2p port of `other` to the peers this node connects to (`addnode`), unlike
+    /// [P2P::Connect] the node keeps accepting other connections and reconnects if the
+    /// connection drops. Returns without waiting the connection, see [BitcoinD::connect_to].
+    pub fn add_node(&self, other: &BitcoinD) -> Result<(), Error> {
+        let addr = other.params.p2p_socket.ok_or(Error::NoP2pPort)?;
+        Ok(self.client.add_node(&addr.to_string())?)
+    }
+
+    /// Connect this node to the p2p port of `other` with `addnode`, waiting for the connection to
+    /// be established
+    pub fn connect_to(&self, other: &BitcoinD) -> anyhow::Result<()> {
+        self.add_node(other)?;
+        let addr = other
+            .params
+            .p2p_socket
+            .expect("checked by add_node")
+            .to_string();
+        wait_for(LINK_TIMEOUT, || {
+            let info = self.client.get_added_node_info(Some(&addr))?;
+            Ok(if info.iter().any(|node| node.connected) {
+                Some(())
+            } else {
+                None
+            })
         })
+        .context("Node not connected to the other one")
+    }
+
+    /// Launch the node again after a recoverable failure, consuming one of the retries
+    fn retry_with_conf<S: AsRef<OsStr>>(exe: S, conf: &Conf) -> anyhow::Result<BitcoinD> {
+        let mut conf = conf.clone();
+        conf.startup.max_port_retries -= 1;
+        Self::with_conf(exe, &conf)
+            .with_context(|| format!("Remaining attempts {}", conf.startup.max_port_retries))
+    }
+
+    /// Returns the user and password of the cookie file, see [ConnectParams::cookie_values]
+    pub fn rpc_cookie(&self) -> Result<(String, String), Error> {
+        self.params.cookie_values()
     }
 
     /// Returns the rpc URL including the schema eg. http://127.0.0.1:44842
@@ -386,120 +2074,1444 @@ impl BitcoinD {
         self.work_dir.path()
     }
 
-    /// Returns the [P2P] enum to connect to this node p2p port
-    pub fn p2p_connect(&self, listen: bool) -> Option<P2P> {
-        self.params.p2p_socket.map(|s| P2P::Connect(s, listen))
+    /// Returns the number of validated headers and blocks of the node as `(headers, blocks)`, during
+    /// a header-first sync the former is ahead of the latter
+    pub fn sync_progress(&self) -> anyhow::Result<(u64, u64)> {
+        let info = self.client.get_blockchain_info()?;
+        Ok((info.headers, info.blocks))
     }
 
-    /// Stop the node, waiting correct process termination
-    pub fn stop(&mut self) -> anyhow::Result<ExitStatus> {
-        self.client.stop()?;
-        Ok(self.process.wait()?)
+    /// Returns the total amount of coins in the UTXO set at the tip, `gettxoutsetinfo` computes
+    /// it scanning the whole set unless the node runs with `-coinstatsindex` (since 22.0). The
+    /// unspendable genesis coinbase is not included.
+    pub fn total_supply(&self) -> anyhow::Result<Amount> {
+        // being generic with `Value` allows to be compatible with different versions
+        let mut info: Value = self.client.call("gettxoutsetinfo", &[])?;
+        // deserialized straight to `Amount` like the amounts of the typed RPC results
+        as_btc::deserialize(info["total_amount"].take())
+            .context("Invalid `total_amount` in gettxoutsetinfo result")
     }
 
-    #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
-    /// Create a new wallet in the running node, and return an RPC client connected to the just
-    /// created wallet
-    pub fn create_wallet<T: AsRef<str>>(&self, wallet: T) -> anyhow::Result<Client> {
-        let _ = self
-            .client
-            .create_wallet(wallet.as_ref(), None, None, None, None)?;
-        Ok(Client::new(
-            &self.rpc_url_with_wallet(wallet),
-            Auth::CookieFile(self.params.cookie_file.clone()),
-        )?)
+    /// Returns the minimum fee rate per kvB for a transaction to be accepted in the mempool
+    /// (`mempoolminfee` of `getmempoolinfo`), it's the relay floor (`-minrelaytxfee`) unless the
+    /// mempool is full and low fee transactions have been evicted
+    pub fn mempool_min_fee(&self) -> anyhow::Result<Amount> {
+        let mut info: Value = self.client.call("getmempoolinfo", &[])?;
+        as_btc::deserialize(info["mempoolminfee"].take())
+            .context("Invalid `mempoolminfee` in getmempoolinfo result")
     }
-}
 
-#[cfg(feature = "download")]
-#[cfg_attr(docsrs, doc(cfg(feature = "download")))]
-impl BitcoinD {
-    /// create BitcoinD struct with the downloaded executable.
-    pub fn from_downloaded() -> anyhow::Result<BitcoinD> {
-        BitcoinD::new(downloaded_exe_path()?)
-    }
-    /// create BitcoinD struct with the downloaded executable and given Conf.
-    pub fn from_downloaded_with_conf(conf: &Conf) -> anyhow::Result<BitcoinD> {
-        BitcoinD::with_conf(downloaded_exe_path()?, conf)
+    /// Returns the version of the running node
+    pub fn version(&self) -> Result<Version, Error> {
+        Ok(Version::from_number(self.client.version()?))
     }
-}
 
-impl Drop for BitcoinD {
-    fn drop(&mut self) {
-        if let DataDir::Persistent(_) = self.work_dir {
-            let _ = self.stop();
-        }
-        let _ = self.process.kill();
+    /// Returns a snapshot of the most asserted-on fields of `getblockchaininfo`
+    pub fn status(&self) -> Result<NodeStatus, Error> {
+        // only the needed fields are parsed, so that it's compatible with every version
+        let info = self.client.call("getblockchaininfo", &[])?;
+        Ok(NodeStatus::from_value(info).map_err(bitcoincore_rpc::Error::from)?)
     }
-}
 
-/// Returns a non-used local port if available.
-///
-/// Note there is a race condition during the time the method check availability and the caller
-pub fn get_available_port() -> anyhow::Result<u16> {
-    // using 0 as port let the system assign a port available
-    let t = TcpListener::bind(("127.0.0.1", 0))?; // 0 means the OS choose a free port
-    Ok(t.local_addr().map(|s| s.port())?)
-}
+    /// Returns statistics about the transactions in the chain and in the window of `window`
+    /// blocks ending at the tip, when `None` the window is about one month of blocks
+    pub fn chain_tx_stats(&self, window: Option<u32>) -> anyhow::Result<ChainTxStats> {
+        let params = match window {
+            Some(window) => vec![window.into()],
+            None => vec![],
+        };
+        let stats = self.client.call("getchaintxstats", &params)?;
+        Ok(ChainTxStats::from_value(stats)?)
+    }
 
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        Error::Io(e)
+    /// Returns the median time past of the tip, the median of the timestamps of the last 11
+    /// blocks. Consensus uses it for time based locktimes and sequence locks instead of the block
+    /// timestamp.
+    pub fn median_time_past(&self) -> anyhow::Result<u64> {
+        Ok(self.client.get_blockchain_info()?.median_time)
     }
-}
 
-impl From<bitcoincore_rpc::Error> for Error {
-    fn from(e: bitcoincore_rpc::Error) -> Self {
-        Error::Rpc(e)
+    /// Wait until the trusted balance of the wallet of [BitcoinD::client] is at least `at_least`,
+    /// returns the balance or [Error::Timeout] if it's not reached within `timeout`
+    pub fn wait_for_balance(&self, at_least: Amount, timeout: Duration) -> Result<Amount, Error> {
+        let start = Instant::now();
+        loop {
+            let balance = self.client.get_balance(None, None)?;
+            if balance >= at_least {
+                return Ok(balance);
+            }
+            if start.elapsed() > timeout {
+                return Err(Error::Timeout(timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
     }
-}
 
-const HAS_FEATURE: bool = cfg!(any(
-    feature = "23_0",
-    feature = "22_0",
-    feature = "0_21_1",
-    feature = "0_21_0",
-    feature = "0_20_1",
-    feature = "0_20_0",
-    feature = "0_19_1",
-    feature = "0_19_0_1",
-    feature = "0_18_1",
-    feature = "0_18_0",
-    feature = "0_17_1",
-));
+    /// Mine `n` blocks to a new address of the wallet of [BitcoinD::client], returning their
+    /// hashes
+    pub fn generate(&self, n: u64) -> Result<Vec<BlockHash>, Error> {
+        let address = self.client.get_new_address(None, None)?;
+        Ok(self.client.generate_to_address(n, &address)?)
+    }
 
-/// Provide the bitcoind executable path if a version feature has been specified
-pub fn downloaded_exe_path() -> anyhow::Result<String> {
-    if HAS_FEATURE {
-        let mut path: PathBuf = env!("OUT_DIR").into();
-        path.push("bitcoin");
-        path.push(format!("bitcoin-{}", versions::VERSION));
-        path.push("bin");
+    /// Mine 101 blocks to the wallet of [BitcoinD::client], so that the first coinbase matures,
+    /// and returns the spendable balance
+    pub fn fund_wallet(&self) -> Result<Amount, Error> {
+        // coinbase outputs are spendable after 100 confirmations
+        self.generate(101)?;
+        Ok(self.client.get_balance(None, None)?)
+    }
 
-        if cfg!(target_os = "windows") {
-            path.push("bitcoind.exe");
-        } else {
-            path.push("bitcoind");
+    /// Returns the next of the addresses generated at startup according to
+    /// [Conf::bootstrap_addresses], when they are exhausted a new address is asked to the wallet
+    pub fn next_address(&self) -> anyhow::Result<Address> {
+        let address = self
+            .address_pool
+            .lock()
+            .expect("poisoned address pool")
+            .pop_front();
+        match address {
+            Some(address) => Ok(address),
+            None => Ok(self.client.get_new_address(None, None)?),
         }
+    }
 
-        Ok(format!("{}", path.display()))
-    } else {
-        Err(Error::NoFeature.into())
+    /// Returns a new address of the given type from the wallet of [BitcoinD::client], regardless
+    /// of [Conf::address_type]
+    pub fn get_new_address_of_type(&self, address_type: AddressType) -> anyhow::Result<Address> {
+        let version = self.client.version()?;
+        if version < address_type.min_version() {
+            return Err(Error::UnsupportedVersion {
+                option: address_type.as_str(),
+                version,
+            }
+            .into());
+        }
+        Ok(self
+            .client
+            .call("getnewaddress", &["".into(), address_type.as_str().into()])?)
     }
-}
 
-/// Returns the daemon executable path if it's provided as a feature or as `BITCOIND_EXE` env var.
-/// Returns error if none or both are set
-pub fn exe_path() -> anyhow::Result<String> {
-    match (downloaded_exe_path(), std::env::var("BITCOIND_EXE")) {
-        (Ok(_), Ok(_)) => Err(Error::BothFeatureAndEnvVar.into()),
-        (Ok(path), Err(_)) => Ok(path),
-        (Err(_), Ok(path)) => Ok(path),
-        (Err(_), Err(_)) => which::which("bitcoind")
-            .map_err(|_| Error::NoBitcoindExecutableFound.into())
-            .map(|p| p.display().to_string()),
+    /// Returns a new change address from the wallet of [BitcoinD::client], of the type set with
+    /// [Conf::change_type] if any. Change addresses are not returned by `getnewaddress`, knowing
+    /// them allows to recognize change outputs.
+    pub fn get_raw_change_address(&self) -> anyhow::Result<Address> {
+        Ok(self.client.call("getrawchangeaddress", &[])?)
     }
-}
+
+    /// Wait until the active chain of the node reaches `height`, eg. because blocks mined by
+    /// another node are received, returns [Error::Timeout] if it's lower after `timeout`
+    pub fn wait_for_block_height(&self, height: u64, timeout: Duration) -> anyhow::Result<()> {
+        wait_for(timeout, || {
+            Ok(if self.client.get_blockchain_info()?.blocks >= height {
+                Some(())
+            } else {
+                None
+            })
+        })
+    }
+
+    /// Wait until `txid` is in the mempool, eg. because it's been relayed by another node,
+    /// returns [Error::Timeout] if it's not there after `timeout`
+    pub fn wait_for_mempool_tx(&self, txid: &Txid, timeout: Duration) -> anyhow::Result<()> {
+        wait_for(timeout, || {
+            Ok(if self.client.get_raw_mempool()?.contains(txid) {
+                Some(())
+            } else {
+                None
+            })
+        })
+    }
+
+    /// Wait until all the optional indexes of the node, like `-txindex` or
+    /// [Conf::block_filter_index], are synced with the tip as reported by `getindexinfo`,
+    /// returns [Error::Timeout] if some are still syncing after `timeout`.
+    /// Requires bitcoind 0.21.0 or later.
+    pub fn wait_for_index_sync(&self, timeout: Duration) -> anyhow::Result<()> {
+        let version = self.client.version()?;
+        if version < 210_000 {
+            return Err(Error::UnsupportedVersion {
+                option: "getindexinfo",
+                version,
+            }
+            .into());
+        }
+        wait_for(timeout, || {
+            let info: HashMap<String, Value> = self.client.call("getindexinfo", &[])?;
+            Ok(if info.values().all(|index| index["synced"] == true) {
+                Some(())
+            } else {
+                None
+            })
+        })
+    }
+
+    /// Wait until `txid` is no longer in the mempool, because it expired, was evicted, replaced
+    /// or mined, returns [Error::Timeout] if it's still there after `timeout`
+    pub fn wait_for_tx_evicted(&self, txid: &Txid, timeout: Duration) -> anyhow::Result<()> {
+        wait_for(timeout, || match self.client.get_mempool_entry(txid) {
+            Ok(_) => Ok(None),
+            // RPC_INVALID_ADDRESS_OR_KEY, "Transaction not in mempool"
+            Err(bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)))
+                if e.code == -5 =>
+            {
+                Ok(Some(()))
+            }
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    /// Returns the fee rate in sat/vB of the transaction `txid`, which must be in the mempool or
+    /// sent by the wallet of [BitcoinD::client], otherwise [Error::UnknownFee] is returned
+    pub fn tx_fee_rate(&self, txid: &Txid) -> anyhow::Result<f64> {
+        if let Ok(entry) = self.client.get_mempool_entry(txid) {
+            return Ok(entry.fees.base.to_sat() as f64 / entry.vsize as f64);
+        }
+        let result = self
+            .client
+            .get_transaction(txid, None)
+            .map_err(|_| Error::UnknownFee(*txid))?;
+        // the fee of wallet transactions is negative
+        let fee = result.fee.ok_or(Error::UnknownFee(*txid))?.abs();
+        let vsize = (result.transaction()?.weight() + 3) / 4;
+        Ok(fee.to_sat() as f64 / vsize as f64)
+    }
+
+    /// Returns the number of confirmations of the transaction `txid`: 0 if it's in the mempool,
+    /// negative if it conflicts with a transaction confirmed that many blocks ago.
+    ///
+    /// Wallet transactions are looked up with `gettransaction`, others with `getrawtransaction`
+    /// which requires `-txindex` unless the transaction is in the mempool
+    pub fn confirmations(&self, txid: &Txid) -> Result<i32, Error> {
+        if let Ok(result) = self.client.get_transaction(txid, None) {
+            return Ok(result.info.confirmations);
+        }
+        let result = self.client.get_raw_transaction_info(txid, None)?;
+        Ok(result.confirmations.unwrap_or(0) as i32)
+    }
+
+    /// Submit the hex serialized block `block_hex` and returns how long `submitblock` took,
+    /// [Error::BlockRejected] if the block is not accepted.
+    ///
+    /// The measure includes the RPC round-trip, which is small compared to the validation of
+    /// big blocks. For a breakdown of the validation time run the node with `-debug=bench`.
+    pub fn time_submit_block(&self, block_hex: &str) -> Result<Duration, Error> {
+        let start = Instant::now();
+        let result: Value = self.client.call("submitblock", &[block_hex.into()])?;
+        let elapsed = start.elapsed();
+        match result.as_str() {
+            Some(reason) => Err(Error::BlockRejected(reason.to_string())),
+            None => Ok(elapsed),
+        }
+    }
+
+    /// Broadcast the hex serialized transaction `tx_hex` expecting the node to reject it with a
+    /// reason containing `reason`, returns [Error::TxAccepted] if it's accepted or
+    /// [Error::RejectReasonMismatch] if it's rejected for another reason
+    pub fn expect_reject(&self, tx_hex: &str, reason: &str) -> Result<(), Error> {
+        match self.client.send_raw_transaction(tx_hex) {
+            Ok(txid) => Err(Error::TxAccepted(txid)),
+            Err(bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e))) => {
+                if e.message.contains(reason) {
+                    Ok(())
+                } else {
+                    Err(Error::RejectReasonMismatch {
+                        expected: reason.to_string(),
+                        actual: e.message,
+                    })
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the unspent output `vout` of the transaction `txid` (`gettxout`), `None` if it's
+    /// spent or unknown. When `include_mempool` is true outputs created by mempool transactions
+    /// are returned and outputs spent by mempool transactions are not.
+    pub fn get_tx_out(
+        &self,
+        txid: &Txid,
+        vout: u32,
+        include_mempool: bool,
+    ) -> Result<Option<GetTxOutResult>, Error> {
+        Ok(self.client.get_tx_out(txid, vout, Some(include_mempool))?)
+    }
+
+    /// Returns the unspent outputs of the wallet of [BitcoinD::client], including unconfirmed and
+    /// locked ones, with their amount and whether they can be spent, which is false for outputs
+    /// locked with `lockunspent` and for watch-only outputs
+    pub fn wallet_utxos(&self) -> Result<Vec<(OutPoint, Amount, bool)>, Error> {
+        let locked = self.client.call("listlockunspent", &[])?;
+        let locked = locked_outpoints(locked).map_err(bitcoincore_rpc::Error::from)?;
+        let mut utxos: Vec<_> = self
+            .client
+            .list_unspent(Some(0), None, None, Some(true), None)?
+            .into_iter()
+            .map(|u| (OutPoint::new(u.txid, u.vout), u.amount, u.spendable))
+            .collect();
+        // locked outputs are not returned by listunspent
+        for outpoint in locked {
+            if let Some(out) = self
+                .client
+                .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))?
+            {
+                utxos.push((outpoint, out.value, false));
+            }
+        }
+        Ok(utxos)
+    }
+
+    /// Returns the most recent `count` transactions of the wallet of [BitcoinD::client], the
+    /// oldest first as returned by `listtransactions`
+    pub fn list_transactions(&self, count: usize) -> anyhow::Result<Vec<ListTransactionResult>> {
+        Ok(self
+            .client
+            .list_transactions(None, Some(count), None, None)?)
+    }
+
+    /// Returns the total bytes received and sent by the node as `(received, sent)`
+    pub fn net_totals(&self) -> anyhow::Result<(u64, u64)> {
+        let totals = self.client.get_net_totals()?;
+        Ok((totals.total_bytes_recv, totals.total_bytes_sent))
+    }
+
+    /// Returns whether the softfork `name` (eg. `segwit`, `csv`, `taproot`) is active at the
+    /// current tip, or [Error::UnknownSoftfork] if the node doesn't know it
+    pub fn is_softfork_active(&self, name: &str) -> anyhow::Result<bool> {
+        // since 23.0 softforks are reported by `getdeploymentinfo`
+        if let Ok(info) = self.client.call::<Value>("getdeploymentinfo", &[]) {
+            return match info["deployments"][name]["active"].as_bool() {
+                Some(active) => Ok(active),
+                None => Err(Error::UnknownSoftfork(name.to_string()).into()),
+            };
+        }
+        let info: Value = self.client.call("getblockchaininfo", &[])?;
+        let softforks = &info["softforks"];
+        let active = if softforks.is_object() {
+            // from 0.19 to 22.0
+            softforks[name]["active"].as_bool()
+        } else {
+            // before 0.19 buried softforks are in an array, bip9 ones in `bip9_softforks`
+            softforks
+                .as_array()
+                .and_then(|forks| forks.iter().find(|f| f["id"] == name))
+                .and_then(|fork| fork["reject"]["status"].as_bool())
+                .or_else(|| {
+                    info["bip9_softforks"][name]["status"]
+                        .as_str()
+                        .map(|status| status == "active")
+                })
+        };
+        active.ok_or_else(|| Error::UnknownSoftfork(name.to_string()).into())
+    }
+
+    /// Returns for how long the node has been running, from the `uptime` RPC
+    pub fn uptime(&self) -> Result<Duration, Error> {
+        Ok(Duration::from_secs(self.client.uptime()?))
+    }
+
+    /// Returns the stdout and stderr written so far by the node with [Output::File], an io error
+    /// of kind `NotFound` is returned with other [Conf::output] modes
+    pub fn log_contents(&self) -> io::Result<String> {
+        fs::read_to_string(&self.launch.output_file)
+    }
+
+    /// Returns the path of the `debug.log` file of the running node
+    pub fn debug_log_path(&self) -> PathBuf {
+        self.params.cookie_file.with_file_name("debug.log")
+    }
+
+    /// Returns the events logged so far in `debug.log`, lines not starting with a timestamp
+    /// (like the continuation of multi-line messages) are skipped.
+    ///
+    /// Launch the node with `-debug=<category>` (or `-debug=1` for all the categories) in
+    /// [Conf::args] to have debug messages logged. Since the first bracketed token after the
+    /// timestamp is taken as the category, `-logthreadnames` should not be used.
+    pub fn log_events(&self) -> anyhow::Result<impl Iterator<Item = LogEvent>> {
+        let file = fs::File::open(self.debug_log_path())?;
+        Ok(BufReader::new(file)
+            .lines()
+            .filter_map(|line| line.ok())
+            .filter_map(|line| LogEvent::parse(&line)))
+    }
+
+    /// Returns the p2p messages exchanged with `peer`, ordered by time. The node must be launched
+    /// with [Conf::capture_messages] and `peer` is the address as reported by the `addr` field of
+    /// `getpeerinfo`, eg. `127.0.0.1:18444`.
+    pub fn captured_messages(&self, peer: &str) -> anyhow::Result<Vec<CapturedMessage>> {
+        let dir = self
+            .params
+            .cookie_file
+            .with_file_name("message_capture")
+            .join(peer.replace(':', "_"));
+        let mut messages = vec![];
+        for (file, outbound) in &[("msgs_recv.dat", false), ("msgs_sent.dat", true)] {
+            let path = dir.join(file);
+            if path.exists() {
+                messages.extend(CapturedMessage::parse_all(&fs::read(path)?, *outbound));
+            }
+        }
+        messages.sort_by_key(|m| m.time);
+        Ok(messages)
+    }
+
+    /// Returns the addresses and subnets banned by the node
+    pub fn list_banned(&self) -> anyhow::Result<Vec<ListBannedResult>> {
+        Ok(self.client.list_banned()?)
+    }
+
+    /// Wait until the node has at least `n` p2p connections, inbound and outbound, returns
+    /// [Error::Timeout] if it doesn't within `timeout`
+    pub fn wait_for_connections(&self, n: usize, timeout: Duration) -> anyhow::Result<()> {
+        wait_for(timeout, || {
+            Ok(if self.client.get_connection_count()? >= n {
+                Some(())
+            } else {
+                None
+            })
+        })
+    }
+
+    /// Wait until `addr` (eg. `127.0.0.1` or `10.0.0.0/8`) is banned, returns the ban entry or
+    /// [Error::Timeout] if it's not banned within `timeout`.
+    ///
+    /// Since 0.20 misbehaving peers are discouraged instead of banned and discouraged peers are not
+    /// reported by `listbanned`, so this only observes bans set with `setban` on recent versions.
+    pub fn wait_for_ban(&self, addr: &str, timeout: Duration) -> anyhow::Result<ListBannedResult> {
+        wait_for(timeout, || {
+            Ok(self.list_banned()?.into_iter().find(|entry| {
+                // single addresses are reported as subnets, eg. `127.0.0.1/32`
+                entry.address == addr
+                    || (entry.address.starts_with(addr)
+                        && entry.address[addr.len()..].starts_with('/'))
+            }))
+        })
+    }
+
+    /// Returns [Error::UnexpectedLogError] if a line of `debug.log` contains `ERROR` and none of
+    /// the strings in `allowlist`. It catches internal failures (eg. while flushing to disk)
+    /// which don't surface through RPC.
+    pub fn assert_no_log_errors(&self, allowlist: &[&str]) -> anyhow::Result<()> {
+        let file = fs::File::open(self.debug_log_path())?;
+        let lines = BufReader::new(file).lines().filter_map(|line| line.ok());
+        match first_unexpected_error(lines, allowlist) {
+            Some(line) => Err(Error::UnexpectedLogError(line).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the [P2P] enum to connect to this node p2p port
+    pub fn p2p_connect(&self, listen: bool) -> Option<P2P> {
+        self.params.p2p_socket.map(|s| P2P::Connect(s, listen))
+    }
+
+    /// Returns the id of the node process, see [std::process::Child::id]
+    pub fn pid(&self) -> u32 {
+        self.process.lock().unwrap_or_else(|e| e.into_inner()).id()
+    }
+
+    /// Returns whether the node process is still running, without blocking. Returns false once
+    /// the node exited, eg. after [BitcoinD::stop] or a crash.
+    pub fn is_running(&self) -> bool {
+        let mut process = self.process.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(None) = process.try_wait() {
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the node process handle
+    fn process(&mut self) -> &mut Child {
+        self.process.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stop the node, waiting correct process termination
+    pub fn stop(&mut self) -> anyhow::Result<ExitStatus> {
+        self.client.stop()?;
+        Ok(self.process().wait()?)
+    }
+
+    /// Stop the node like [BitcoinD::stop], returning [Error::UncleanExit] if the process doesn't
+    /// exit successfully, eg. because it crashed while shutting down
+    pub fn stop_checked(&mut self) -> anyhow::Result<()> {
+        let status = self.stop()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::UncleanExit(status).into())
+        }
+    }
+
+    /// Kill the node process without asking it to shut down, useful when the node is
+    /// unresponsive or to test recovery after a crash. The data directory may need to be
+    /// recovered by the node at the next start.
+    pub fn kill(&mut self) -> Result<(), Error> {
+        let process = self.process();
+        if process.try_wait()?.is_none() {
+            process.kill()?;
+            process.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Stop the node and launch it again with the same executable, arguments and data
+    /// directory, waiting for it to be ready and loading the wallet of [Conf::wallet].
+    ///
+    /// The previous RPC port is reused so that [BitcoinD::rpc_url] given to other services keeps
+    /// working, if it has been taken in the meantime, noticed by the node exiting early, a new
+    /// port is used up to [StartupPolicy::max_port_retries] times and [BitcoinD::params] and
+    /// [BitcoinD::client] are updated accordingly. The p2p port is always reused.
+    pub fn restart(&mut self) -> anyhow::Result<()> {
+        self.restart_with_args(&[])
+    }
+
+    /// Like [BitcoinD::restart] but the relaunched process is given also `args`, eg. `-reindex`.
+    /// The `args` are not kept, a following restart uses only the original arguments. Options
+    /// set by this crate are rejected like in [Conf::args].
+    pub fn restart_with_args(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        let args = validate_args(args.to_vec())?;
+        check_reserved(
+            args.iter().cloned(),
+            &self.launch.reserved,
+            self.launch.network,
+        )?;
+        self.stop()?;
+        // the previous port is tried first, checking it's free beforehand would be racy, an early
+        // exit or a timeout is taken as the port being taken and a new one is tried, at most
+        // `max_port_retries` times
+        let mut rpc_port = self.params.rpc_socket.port();
+        let mut launch = self.launch.clone();
+        loop {
+            let process = launch.spawn(rpc_port, true, &args)?;
+            let mut params = self.params.clone();
+            params.rpc_socket = SocketAddrV4::new(LOCAL_IP, rpc_port);
+            let mut starting = Starting::new(process, launch.clone(), params);
+            match starting.wait_ready()? {
+                Some(client) => {
+                    self.process = Mutex::new(starting.process);
+                    self.params = starting.params;
+                    self.client = client;
+                    return Ok(());
+                }
+                None => {
+                    launch.startup.max_port_retries -= 1;
+                    let port = get_available_port()?;
+                    warn!(
+                        "rpc port {} may have been taken, restarting on port {}",
+                        rpc_port, port
+                    );
+                    rpc_port = port;
+                }
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
+    /// Create a new wallet in the running node, and return an RPC client connected to the just
+    /// created wallet. It's a descriptor wallet according to [Conf::wallet_descriptors].
+    pub fn create_wallet<T: AsRef<str>>(&self, wallet: T) -> anyhow::Result<Client> {
+        create_wallet(&self.client, wallet.as_ref(), self.launch.descriptors)?;
+        Ok(self.params.client(&self.rpc_url_with_wallet(wallet))?)
+    }
+
+    #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
+    /// Return an RPC client connected to the wallet `wallet` already loaded in the running node,
+    /// or [Error::WalletNotLoaded]. The client uses the same authentication of [BitcoinD::client].
+    pub fn wallet_client<T: AsRef<str>>(&self, wallet: T) -> anyhow::Result<Client> {
+        let wallets = self.client.list_wallets()?;
+        if !wallets.iter().any(|w| w == wallet.as_ref()) {
+            return Err(Error::WalletNotLoaded(wallet.as_ref().to_string()).into());
+        }
+        Ok(self.params.client(&self.rpc_url_with_wallet(wallet))?)
+    }
+
+    /// Return an RPC client connected to [BitcoinD::rpc_url] followed by `path`, eg. `""` or `"/"`
+    /// for node level calls and `"/wallet/name"` for a wallet created outside this crate. The
+    /// client uses the same authentication of [BitcoinD::client].
+    pub fn client_for_path(&self, path: &str) -> Result<Client, Error> {
+        Ok(self.params.client(&format!("{}{}", self.rpc_url(), path))?)
+    }
+
+    /// Compare the best block hash of this node with the one of the `other` node, returning
+    /// [Error::TipMismatch] if they differ
+    pub fn assert_same_tip_as(&self, other: &BitcoinD) -> Result<(), Error> {
+        let this = self.client.get_best_block_hash()?;
+        let other = other.client.get_best_block_hash()?;
+        if this != other {
+            return Err(Error::TipMismatch { this, other });
+        }
+        Ok(())
+    }
+
+    /// Wait until this node and the `other` node have the same transactions in their mempools,
+    /// returns [Error::Timeout] if they still differ after `timeout`
+    pub fn wait_for_mempool_sync_with(
+        &self,
+        other: &BitcoinD,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        wait_for(timeout, || {
+            let this: HashSet<Txid> = self.client.get_raw_mempool()?.into_iter().collect();
+            let other: HashSet<Txid> = other.client.get_raw_mempool()?.into_iter().collect();
+            Ok(if this == other { Some(()) } else { None })
+        })
+    }
+
+    /// Create a transaction paying `amount` to `address` with the given `locktime`, funded and
+    /// signed by the wallet of [BitcoinD::client], and return its hex serialization.
+    ///
+    /// All the inputs get the given `sequence`, which can be used to encode a CSV relative
+    /// timelock. When `None` the inputs are set to `0xfffffffe` so that `locktime` is enforced.
+    pub fn create_timelocked_tx(
+        &self,
+        address: &Address,
+        amount: Amount,
+        locktime: u32,
+        sequence: Option<u32>,
+    ) -> anyhow::Result<String> {
+        let mut outs = HashMap::new();
+        outs.insert(address.to_string(), amount);
+        let raw =
+            self.client
+                .create_raw_transaction_hex(&[], &outs, Some(locktime as i64), None)?;
+        let funded = self.client.fund_raw_transaction(raw, None, None)?;
+        let mut tx: Transaction = deserialize(&funded.hex)?;
+        let sequence = Sequence(sequence.unwrap_or(0xFFFF_FFFE));
+        for input in tx.input.iter_mut() {
+            input.sequence = sequence;
+        }
+        let signed = self
+            .client
+            .sign_raw_transaction_with_wallet(&tx, None, None)?;
+        if !signed.complete {
+            return Err(Error::IncompleteSignature.into());
+        }
+        Ok(signed.hex.to_hex())
+    }
+
+    /// Broadcast low fee transactions until the memory usage of the mempool reaches
+    /// `target_fraction` of `-maxmempool` and return their txids, useful to set up eviction and
+    /// minimum fee tests. Use [Conf::max_mempool_mb] to keep the number of transactions needed low.
+    ///
+    /// Every transaction spends a confirmed output of the wallet of [BitcoinD::client], the outputs
+    /// needed are created and confirmed beforehand, mining blocks to fund them if the balance is
+    /// not enough.
+    pub fn fill_mempool(&self, target_fraction: f64) -> anyhow::Result<Vec<Txid>> {
+        if !(target_fraction > 0.0 && target_fraction <= 1.0) {
+            return Err(Error::InvalidFraction(target_fraction).into());
+        }
+        let mempool_usage = || -> anyhow::Result<(u64, u64)> {
+            let info: Value = self.client.call("getmempoolinfo", &[])?;
+            let usage = info["usage"]
+                .as_u64()
+                .context("Missing `usage` in getmempoolinfo result")?;
+            let max = info["maxmempool"]
+                .as_u64()
+                .context("Missing `maxmempool` in getmempoolinfo result")?;
+            Ok((usage, max))
+        };
+        let (_, max) = mempool_usage()?;
+        let target = (max as f64 * target_fraction) as u64;
+
+        // a transaction uses more memory in the mempool than its size
+        let needed = target / (FILL_OUTPUTS as u64 * 31) + 1;
+        let address = self.client.get_new_address(None, None)?;
+        let script_pubkey = address.script_pubkey();
+        let inputs = self.fan_out(&address, Amount::from_sat(FILL_INPUT_SAT), needed as usize)?;
+
+        let fee = self.mempool_min_fee()?.to_sat() * 2 * FILL_TX_MAX_VSIZE / 1_000;
+        let output = TxOut {
+            value: (FILL_INPUT_SAT - fee) / FILL_OUTPUTS as u64,
+            script_pubkey,
+        };
+        let mut txids = vec![];
+        for previous_output in inputs {
+            if mempool_usage()?.0 >= target {
+                break;
+            }
+            let tx = Transaction {
+                version: 2,
+                lock_time: PackedLockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output,
+                    ..Default::default()
+                }],
+                output: vec![output.clone(); FILL_OUTPUTS],
+            };
+            let signed = self
+                .client
+                .sign_raw_transaction_with_wallet(&tx, None, None)?;
+            if !signed.complete {
+                return Err(Error::IncompleteSignature.into());
+            }
+            txids.push(self.client.send_raw_transaction(&signed.hex)?);
+        }
+        Ok(txids)
+    }
+
+    /// Create and confirm `count` outputs of `value` paying to `address`, funded by the wallet
+    /// of [BitcoinD::client], mining blocks if the balance is not enough
+    fn fan_out(
+        &self,
+        address: &Address,
+        value: Amount,
+        count: usize,
+    ) -> anyhow::Result<Vec<OutPoint>> {
+        let mut outpoints = Vec::with_capacity(count);
+        while outpoints.len() < count {
+            // stay well below the maximum standard transaction size
+            let n = (count - outpoints.len()).min(1_000);
+            while self.client.get_balance(None, None)? < value * (n as u64 + 1) {
+                let blocks = if self.client.get_block_count()? < 100 {
+                    101
+                } else {
+                    1
+                };
+                self.client.generate_to_address(blocks, address)?;
+            }
+            let tx = Transaction {
+                version: 2,
+                lock_time: PackedLockTime::ZERO,
+                input: vec![],
+                output: vec![
+                    TxOut {
+                        value: value.to_sat(),
+                        script_pubkey: address.script_pubkey(),
+                    };
+                    n
+                ],
+            };
+            let funded = self.client.fund_raw_transaction(&tx, None, Some(false))?;
+            let signed = self
+                .client
+                .sign_raw_transaction_with_wallet(&funded.hex, None, None)?;
+            if !signed.complete {
+                return Err(Error::IncompleteSignature.into());
+            }
+            let txid = self.client.send_raw_transaction(&signed.hex)?;
+            let change = funded.change_position;
+            outpoints.extend(
+                (0..=n as u32)
+                    .filter(|vout| *vout as i32 != change)
+                    .take(n)
+                    .map(|vout| OutPoint::new(txid, vout)),
+            );
+        }
+        self.client.generate_to_address(1, address)?;
+        Ok(outpoints)
+    }
+
+    #[cfg(not(feature = "0_17_1"))]
+    /// Returns the address at position `index` of a fixed descriptor, the same index always gives
+    /// the same address across runs, eg. index 0 is `bcrt1qp5wfcq48h6d63wyy9qz0awtpfqwwv4sm4gc9mc`
+    /// on regtest.
+    ///
+    /// Addresses are derived by the node with `deriveaddresses` from the descriptor
+    /// `wpkh(tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp/0/*)`,
+    /// where the extended public key is the master key of the BIP32 test vector 1 seed. Since the
+    /// private key is public knowledge, these addresses are meant to be asserted against, the
+    /// wallet of the node doesn't own them.
+    pub fn deterministic_address(&self, index: u32) -> anyhow::Result<Address> {
+        let mut addresses =
+            self.derive_addresses(DETERMINISTIC_DESCRIPTOR, Some((index, index)))?;
+        addresses
+            .pop()
+            .with_context(|| format!("deriveaddresses returned no address for index {}", index))
+    }
+
+    #[cfg(not(feature = "0_17_1"))]
+    /// Returns the addresses derived from `descriptor` with `deriveaddresses`, without any wallet
+    /// involved. For ranged descriptors `range` is the inclusive range of indexes to derive.
+    ///
+    /// The checksum is appended to `descriptor` with `getdescriptorinfo` if missing.
+    pub fn derive_addresses(
+        &self,
+        descriptor: &str,
+        range: Option<(u32, u32)>,
+    ) -> anyhow::Result<Vec<Address>> {
+        let descriptor = if descriptor.contains('#') {
+            descriptor.to_string()
+        } else {
+            let info = self.client.get_descriptor_info(descriptor)?;
+            format!("{}#{}", descriptor, info.checksum)
+        };
+        let range = range.map(|(start, end)| [start, end]);
+        Ok(self.client.derive_addresses(&descriptor, range)?)
+    }
+
+    /// Returns `true` if the transaction with the given `txid` is included in the block with the
+    /// given `block_hash`, the block doesn't need to be in the active chain
+    pub fn is_tx_in_block(&self, txid: &Txid, block_hash: &BlockHash) -> anyhow::Result<bool> {
+        let block = self.client.get_block_info(block_hash)?;
+        Ok(block.tx.contains(txid))
+    }
+
+    /// Returns the hex of the 80 bytes serialized header of the block `hash`
+    pub fn get_block_header_hex(&self, hash: &BlockHash) -> anyhow::Result<String> {
+        Ok(self
+            .client
+            .call("getblockheader", &[hash.to_string().into(), false.into()])?)
+    }
+
+    /// Returns the hex of the serialized headers of up to `count` blocks starting from `start`,
+    /// following the active chain via the `nextblockhash` of each header. Less than `count`
+    /// headers are returned if the tip is reached.
+    pub fn get_headers(&self, start: &BlockHash, count: usize) -> anyhow::Result<Vec<String>> {
+        let mut headers = Vec::with_capacity(count);
+        let mut next = Some(*start);
+        while let Some(hash) = next {
+            if headers.len() == count {
+                break;
+            }
+            headers.push(self.get_block_header_hex(&hash)?);
+            next = self.client.get_block_header_info(&hash)?.next_block_hash;
+        }
+        Ok(headers)
+    }
+
+    /// Returns the BIP158 compact block filter of the block `hash` (`getblockfilter`), requires
+    /// [Conf::block_filter_index]. The filter of a block just mined may be missing until the
+    /// index catches up with the tip, see [BitcoinD::wait_for_index_sync].
+    pub fn get_block_filter(&self, hash: &BlockHash) -> Result<GetBlockFilterResult, Error> {
+        Ok(self.client.get_block_filter(hash)?)
+    }
+
+    /// Returns the coinbase transaction of the block with the given `block_hash`
+    pub fn coinbase_tx(&self, block_hash: &BlockHash) -> anyhow::Result<Transaction> {
+        let block = self.client.get_block(block_hash)?;
+        block
+            .txdata
+            .into_iter()
+            .next()
+            .with_context(|| format!("Block {} without transactions", block_hash))
+    }
+
+    /// Decode the given transaction `hex` with the `decoderawtransaction` RPC
+    pub fn decode_raw_transaction(&self, hex: &str) -> anyhow::Result<DecodeRawTransactionResult> {
+        let decoded = self.client.call("decoderawtransaction", &[hex.into()])?;
+        Ok(DecodeRawTransactionResult::from_value(decoded)?)
+    }
+
+    /// Create a PSBT paying the given `outputs` with inputs selected by the wallet of
+    /// [BitcoinD::client], returns the base64 encoded PSBT
+    pub fn wallet_create_funded_psbt(
+        &self,
+        outputs: &[(Address, Amount)],
+    ) -> anyhow::Result<String> {
+        let outputs: HashMap<String, Amount> = outputs
+            .iter()
+            .map(|(address, amount)| (address.to_string(), *amount))
+            .collect();
+        let result = self
+            .client
+            .wallet_create_funded_psbt(&[], &outputs, None, None, None)?;
+        Ok(result.psbt)
+    }
+
+    /// Update the given base64 `psbt` with the information known by the wallet of
+    /// [BitcoinD::client] and sign the inputs it can, returns the base64 encoded PSBT
+    pub fn wallet_process_psbt(&self, psbt: &str) -> anyhow::Result<String> {
+        let result = self
+            .client
+            .wallet_process_psbt(psbt, Some(true), None, None)?;
+        Ok(result.psbt)
+    }
+
+    /// Finalize the given base64 `psbt` and return the hex of the extracted network
+    /// transaction, returns [Error::IncompleteSignature] if some inputs are not signed
+    pub fn finalize_psbt(&self, psbt: &str) -> anyhow::Result<String> {
+        let result = self.client.finalize_psbt(psbt, Some(true))?;
+        match result.hex {
+            Some(hex) if result.complete => Ok(hex.to_hex()),
+            _ => Err(Error::IncompleteSignature.into()),
+        }
+    }
+
+    /// Encrypt the wallet of [BitcoinD::client] with `passphrase`, the wallet is locked afterwards.
+    ///
+    /// Before 0.16 bitcoind shut down after `encryptwallet`, every supported version keeps
+    /// running so the node is usable right away without a restart.
+    pub fn encrypt_wallet(&self, passphrase: &str) -> anyhow::Result<()> {
+        // `RpcApi::encrypt_wallet` can't parse the message returned by bitcoind
+        let _: Value = self.client.call("encryptwallet", &[passphrase.into()])?;
+        Ok(())
+    }
+
+    /// Unlock the encrypted wallet of [BitcoinD::client] for `timeout`
+    pub fn wallet_passphrase(&self, passphrase: &str, timeout: Duration) -> anyhow::Result<()> {
+        let _: Value = self.client.call(
+            "walletpassphrase",
+            &[passphrase.into(), timeout.as_secs().into()],
+        )?;
+        Ok(())
+    }
+
+    /// Lock the encrypted wallet of [BitcoinD::client]
+    pub fn wallet_lock(&self) -> anyhow::Result<()> {
+        let _: Value = self.client.call("walletlock", &[])?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+impl BitcoinD {
+    /// Like [BitcoinD::new] but awaiting the node to be ready without blocking the tokio runtime
+    pub async fn new_async<S: AsRef<OsStr>>(exe: S) -> anyhow::Result<BitcoinD> {
+        BitcoinD::with_conf_async(exe, Conf::default()).await
+    }
+
+    /// Like [BitcoinD::with_conf] but awaiting the node to be ready without blocking the tokio
+    /// runtime: the readiness poll awaits `tokio::time::sleep` and the blocking launch and RPC
+    /// probes run with `spawn_blocking`. The returned [BitcoinD] keeps the blocking
+    /// [BitcoinD::client], wrap its calls in `spawn_blocking` too if needed.
+    ///
+    /// The conf is moved to the blocking tasks, thus its borrowed fields must be `'static`.
+    pub async fn with_conf_async<S: AsRef<OsStr>>(
+        exe: S,
+        mut conf: Conf<'static>,
+    ) -> anyhow::Result<BitcoinD> {
+        use tokio::task::spawn_blocking;
+        let mut exe = exe.as_ref().to_os_string();
+        let retries = conf.startup.max_port_retries;
+        loop {
+            let (spawn_exe, spawn_conf) = (exe.clone(), conf.clone());
+            let (mut starting, work_dir) =
+                spawn_blocking(move || BitcoinD::spawn_node(&spawn_exe, &spawn_conf)).await??;
+            let mut wait = conf.startup.poll_interval;
+            let client = loop {
+                tokio::time::sleep(wait).await;
+                let (returned, step) = spawn_blocking(move || {
+                    let step = starting.step();
+                    (starting, step)
+                })
+                .await?;
+                starting = returned;
+                match step {
+                    Ok(StartupStep::Wait(next)) => wait = next,
+                    Ok(StartupStep::Ready(client)) => break Some(client),
+                    Ok(StartupStep::Retry) => {
+                        conf.startup.max_port_retries -= 1;
+                        exe = starting.launch.exe.clone();
+                        break None;
+                    }
+                    Err(e) if conf.startup.max_port_retries < retries => {
+                        let remaining = conf.startup.max_port_retries;
+                        return Err(e.context(format!("Remaining attempts {}", remaining)));
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            if let Some(client) = client {
+                let conf = conf.clone();
+                return spawn_blocking(move || starting.finish(client, work_dir, &conf)).await?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "download")]
+#[cfg_attr(docsrs, doc(cfg(feature = "download")))]
+impl BitcoinD {
+    /// create BitcoinD struct with the downloaded executable.
+    pub fn from_downloaded() -> anyhow::Result<BitcoinD> {
+        BitcoinD::new(downloaded_exe_path()?)
+    }
+    /// create BitcoinD struct with the downloaded executable and given Conf.
+    pub fn from_downloaded_with_conf(conf: &Conf) -> anyhow::Result<BitcoinD> {
+        BitcoinD::with_conf(downloaded_exe_path()?, conf)
+    }
+    /// create BitcoinD struct with the downloaded executable, checking it's the given `version`
+    /// like `"23.0"`.
+    ///
+    /// Nothing is downloaded by this function: the executable is downloaded and verified at build
+    /// time, so `version` must be the one selected with the version feature (eg. `23_0`). An
+    /// error listing the [SUPPORTED_VERSIONS] is returned for unknown versions.
+    pub fn from_downloaded_checking_version(version: &str) -> anyhow::Result<BitcoinD> {
+        check_downloaded_version(version, versions::VERSION)?;
+        BitcoinD::from_downloaded()
+    }
+}
+
+impl Drop for BitcoinD {
+    fn drop(&mut self) {
+        // a killed node may leave the block index corrupted, making the data directory unusable
+        // when it's reused, so try a clean shutdown first
+        if self.client.stop().is_ok() {
+            let start = Instant::now();
+            while start.elapsed() < SHUTDOWN_TIMEOUT {
+                if let Ok(Some(_)) = self.process().try_wait() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+        let _ = self.process().kill();
+        let _ = self.process().wait();
+        if self.keep_datadir_on_panic && thread::panicking() {
+            let work_dir =
+                std::mem::replace(&mut self.work_dir, DataDir::Persistent(PathBuf::new()));
+            let path = match work_dir {
+                // `keep` replacing it is missing in the tempfile versions supporting the MSRV
+                #[allow(deprecated)]
+                DataDir::Temporary(tmp_dir) => tmp_dir.into_path(),
+                DataDir::Persistent(path) | DataDir::Reused(path) => path,
+            };
+            eprintln!(
+                "bitcoind data directory kept for inspection: {}",
+                path.display()
+            );
+            return;
+        }
+        if let DataDir::Reused(path) = &self.work_dir {
+            // the process is gone, the next node can use the directory
+            let _ = clear_dir(path);
+        }
+    }
+}
+
+/// Returns whether the node is ready according to the `startup` policy
+fn node_ready(client: &Client, startup: &StartupPolicy) -> bool {
+    // RpcApi has get_blockchain_info method, however being generic with `Value` allows
+    // to be compatible with different version
+    let info = match client.call::<Value>("getblockchaininfo", &[]) {
+        Ok(info) => info,
+        Err(_) => return false,
+    };
+    !startup.wait_for_full_sync || fully_synced(&info)
+}
+
+/// Returns whether the `getblockchaininfo` result reports all the known headers validated
+fn fully_synced(info: &Value) -> bool {
+    let blocks = info["blocks"].as_u64();
+    let synced = blocks.is_some() && blocks == info["headers"].as_u64();
+    let progress = info["verificationprogress"].as_f64().unwrap_or(0.0);
+    // the progress is an estimate, it may be slightly below 1 at the tip
+    synced && progress > 0.9999
+}
+
+/// Create the `wallet` wallet, a descriptor wallet if `descriptors` is `Some(true)`, a legacy
+/// one if `Some(false)` or the bitcoind default if `None`
+fn create_wallet(
+    client: &Client,
+    wallet: &str,
+    descriptors: Option<bool>,
+) -> Result<(), bitcoincore_rpc::Error> {
+    match descriptors {
+        // the descriptors argument of createwallet is not supported by the rpc client
+        Some(descriptors) => {
+            let args = [
+                wallet.into(),
+                false.into(),
+                false.into(),
+                "".into(),
+                false.into(),
+                descriptors.into(),
+            ];
+            client.call::<Value>("createwallet", &args)?;
+        }
+        None => {
+            client.create_wallet(wallet, None, None, None, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create the `wallet` wallet or load it if it already exists, a wallet already loaded (eg.
+/// with `-wallet=default` in a persistent data directory) is not an error
+fn create_or_load_wallet(
+    client: &Client,
+    wallet: &str,
+    descriptors: Option<bool>,
+) -> Result<(), Error> {
+    match create_wallet(client, wallet, descriptors) {
+        Ok(()) => return Ok(()),
+        Err(e) if wallet_already_exists(&e) => (),
+        Err(e) => return Err(e.into()),
+    }
+    match client.load_wallet(wallet) {
+        Ok(_) => Ok(()),
+        Err(bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)))
+            // RPC_WALLET_ALREADY_LOADED, before 0.21 loading a loaded wallet gives a generic error
+            if e.code == -35
+                || e.message.contains("already loaded")
+                || e.message.contains("Duplicate -wallet filename") =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns whether `createwallet` failed because the wallet exists in the wallets directory, like
+/// in a reused or copied datadir
+fn wallet_already_exists(error: &bitcoincore_rpc::Error) -> bool {
+    match error {
+        // "Wallet {} already exists." before 0.21, "Database already exists." since
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)) => {
+            e.message.contains("already exists")
+        }
+        _ => false,
+    }
+}
+
+/// Returns the argument activating taproot at `height`, supported by bitcoind 0.21.1 or later.
+/// Signalling starts at genesis and never times out, `height` is the minimum activation height
+fn taproot_activation_arg(height: u32) -> String {
+    format!("-vbparams=taproot:0:{}:{}", i64::max_value(), height)
+}
+
+/// Returns the `-rpcauth` argument for `user` and `password`, hashed with `salt` the same way of
+/// bitcoind `share/rpcauth/rpcauth.py`
+fn rpcauth_arg(user: &str, password: &str, salt: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(salt.as_bytes());
+    engine.input(password.as_bytes());
+    let hash = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    format!("-rpcauth={}:{}${}", user, salt, hash.to_hex())
+}
+
+/// Returns a hash different at every call, also across processes
+fn unique_hash() -> sha256::Hash {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&nanos.to_le_bytes());
+    engine.input(&std::process::id().to_le_bytes());
+    engine.input(&COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+/// Returns a hex salt for [rpcauth_arg], unique enough to not be shared by nodes launched
+/// concurrently
+fn random_salt() -> String {
+    unique_hash()[..16].to_hex()
+}
+
+/// Returns a [Conf::signet_challenge] different at every call, giving a unique network magic to
+/// the nodes using it.
+///
+/// The challenge is a random 32 bytes push dropped before `OP_TRUE`, so it's satisfied by an
+/// empty block solution.
+pub fn unique_signet_challenge() -> String {
+    // OP_PUSHBYTES_32 <random> OP_DROP OP_TRUE
+    format!("20{}7551", unique_hash().to_hex())
+}
+
+/// Copies `output` to `file` and to stdout from a background thread, until `output` is closed
+fn tee<R: Read + Send + 'static>(mut output: R, mut file: fs::File) {
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match output.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let _ = file.write_all(&buf[..n]);
+                    let _ = io::stdout().write_all(&buf[..n]);
+                }
+            }
+        }
+    });
+}
+
+/// Returns the first of `lines` containing `ERROR` and none of the strings in `allowlist`
+fn first_unexpected_error<I>(mut lines: I, allowlist: &[&str]) -> Option<String>
+where
+    I: Iterator<Item = String>,
+{
+    lines.find(|line| line.contains("ERROR") && !allowlist.iter().any(|a| line.contains(a)))
+}
+
+/// Removes everything in `dir`, creating it if it doesn't exist
+fn clear_dir(dir: &Path) -> anyhow::Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+/// Files written by a running node, not copied from a snapshot by [copy_dir]
+const SNAPSHOT_SKIPPED_FILES: [&str; 3] = [".cookie", ".lock", "bitcoind.pid"];
+
+/// Recursively copies the content of the directory `from` into `to`, except the
+/// [SNAPSHOT_SKIPPED_FILES] which are written by the node at every start
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if !SNAPSHOT_SKIPPED_FILES.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` every 100 milliseconds until it returns a value or an error, returns
+/// [Error::Timeout] if `timeout` elapses before
+fn wait_for<T, F>(timeout: Duration, mut f: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> anyhow::Result<Option<T>>,
+{
+    let start = Instant::now();
+    loop {
+        if let Some(value) = f()? {
+            return Ok(value);
+        }
+        if start.elapsed() > timeout {
+            return Err(Error::Timeout(timeout).into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Returns a non-used local port if available.
+///
+/// Note there is a race condition during the time the method check availability and the caller
+/// binds the port, use [reserve_port] to keep the port reserved until it's needed.
+pub fn get_available_port() -> anyhow::Result<u16> {
+    Ok(reserve_port()?.local_addr()?.port())
+}
+
+/// Returns a listener bound to a non-used local port, the port is reserved (the OS will not
+/// assign it to anyone else) until the listener is dropped.
+///
+/// [BitcoinD::with_conf] holds the listeners of the ports it assigns and drops them right before
+/// spawning the node, shrinking the window in which another process could take them.
+pub fn reserve_port() -> anyhow::Result<TcpListener> {
+    // using 0 as port let the system assign a port available
+    Ok(TcpListener::bind((LOCAL_IP, 0))?)
+}
+
+/// Returns the p2p socket the node will listen on and the related args, `bind` is used if given
+/// otherwise a non-used local port is reserved and its listener pushed in `reserved`
+fn p2p_listen_args(
+    bind: Option<SocketAddrV4>,
+    reserved: &mut Vec<TcpListener>,
+) -> anyhow::Result<(SocketAddrV4, Vec<String>)> {
+    match bind {
+        Some(bind) => {
+            let args = vec![format!("-port={}", bind.port()), format!("-bind={}", bind)];
+            Ok((bind, args))
+        }
+        None => {
+            let p2p_socket = reserve_socket(reserved)?;
+            Ok((p2p_socket, vec![format!("-port={}", p2p_socket.port())]))
+        }
+    }
+}
+
+/// Reserves a non-used local port with [reserve_port], keeping the listener in `reserved`
+fn reserve_socket(reserved: &mut Vec<TcpListener>) -> anyhow::Result<SocketAddrV4> {
+    let listener = reserve_port()?;
+    let port = listener.local_addr()?.port();
+    reserved.push(listener);
+    Ok(SocketAddrV4::new(LOCAL_IP, port))
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bitcoincore_rpc::Error> for Error {
+    fn from(e: bitcoincore_rpc::Error) -> Self {
+        Error::Rpc(e)
+    }
+}
+
+const HAS_FEATURE: bool = cfg!(any(
+    feature = "23_0",
+    feature = "22_0",
+    feature = "0_21_1",
+    feature = "0_21_0",
+    feature = "0_20_1",
+    feature = "0_20_0",
+    feature = "0_19_1",
+    feature = "0_19_0_1",
+    feature = "0_18_1",
+    feature = "0_18_0",
+    feature = "0_17_1",
+));
+
+/// Returns an error if `requested` is not a supported version or it's not the `downloaded` one
+#[cfg_attr(not(feature = "download"), allow(dead_code))]
+fn check_downloaded_version(requested: &str, downloaded: &'static str) -> Result<(), Error> {
+    if !SUPPORTED_VERSIONS.contains(&requested) {
+        return Err(Error::UnknownVersion(requested.to_string()));
+    }
+    if requested != downloaded {
+        return Err(Error::VersionNotDownloaded {
+            requested: requested.to_string(),
+            downloaded,
+        });
+    }
+    Ok(())
+}
+
+/// Provide the bitcoind executable path if a version feature has been specified.
+///
+/// The executable is downloaded by the build script in the build directory, or in the directory
+/// given with the `BITCOIND_DOWNLOAD_DIR` env var at build time to reuse it across builds.
+pub fn downloaded_exe_path() -> anyhow::Result<String> {
+    if HAS_FEATURE {
+        let mut path: PathBuf = match option_env!("BITCOIND_EXE_HOME") {
+            Some(home) => home.into(),
+            None => Path::new(env!("OUT_DIR")).join("bitcoin"),
+        };
+        path.push(format!("bitcoin-{}", versions::VERSION));
+        path.push("bin");
+
+        if cfg!(target_os = "windows") {
+            path.push("bitcoind.exe");
+        } else {
+            path.push("bitcoind");
+        }
+
+        Ok(format!("{}", path.display()))
+    } else {
+        Err(Error::NoFeature.into())
+    }
+}
+
+/// Returns the daemon executable path if it's provided as a feature or as `BITCOIND_EXE` env var.
+/// Returns error if none or both are set
+pub fn exe_path() -> anyhow::Result<String> {
+    match (downloaded_exe_path(), std::env::var("BITCOIND_EXE")) {
+        (Ok(_), Ok(_)) => Err(Error::BothFeatureAndEnvVar.into()),
+        (Ok(path), Err(_)) => Ok(path),
+        (Err(_), Ok(path)) => Ok(path),
+        (Err(_), Err(_)) => which::which("bitcoind")
+            .map_err(|_| Error::NoBitcoindExecutableFound.into())
+            .map(|p| p.display().to_string()),
+    }
+}
+
+/// Parses the content of the cookie file, formatted as `user:password`
+fn parse_cookie(content: &str) -> Result<(String, String), Error> {
+    let mut values = content.trim_end().splitn(2, ':');
+    match (values.next(), values.next()) {
+        (Some(user), Some(password)) => Ok((user.to_string(), password.to_string())),
+        _ => Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "cookie file content is not in the `user:password` format",
+        ))),
+    }
+}
+
+/// Returns the path of the executable given with the `BITCOIND_EXE` env var, checking it exists
+/// (appending `.exe` on Windows if needed)
+pub fn exe_from_env() -> Result<PathBuf, Error> {
+    let exe = env::var_os("BITCOIND_EXE").ok_or(Error::NoEnvVar)?;
+    resolve_exe(&exe).map(PathBuf::from)
+}
+
+/// Returns the executable to launch for the given `exe`: the path itself if it exists, otherwise
+/// the path with the platform executable suffix (`.exe` on Windows) or, for a bare name like
+/// `bitcoind`, the executable found in the `PATH`
+fn resolve_exe(exe: &OsStr) -> Result<OsString, Error> {
+    let path = Path::new(exe);
+    if path.is_file() {
+        return Ok(exe.to_os_string());
+    }
+    let suffix = env::consts::EXE_SUFFIX;
+    if !suffix.is_empty() && path.extension().is_none() {
+        let mut with_suffix = exe.to_os_string();
+        with_suffix.push(suffix);
+        if Path::new(&with_suffix).is_file() {
+            return Ok(with_suffix);
+        }
+    }
+    if path.components().count() == 1 {
+        if let Ok(found) = which::which(path) {
+            return Ok(found.into_os_string());
+        }
+    }
+    Err(Error::ExeNotFound(path.to_path_buf()))
+}
+
+/// Returns the version of the given bitcoind executable, in the same format of the `version`
+/// returned by the `getnetworkinfo` RPC, eg. `230000` for `23.0`
+fn exe_version(exe: &OsStr) -> anyhow::Result<usize> {
+    let output = Command::new(exe)
+        .arg("-version")
+        .output()
+        .with_context(|| format!("Error while executing {:?}", exe))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version(&stdout)
+        .with_context(|| format!("Cannot parse version of {:?} from: {}", exe, stdout))
+}
+
+/// Parses the version from the first line of `bitcoind -version` output like
+/// `Bitcoin Core version v23.0.0` or `Bitcoin Core Daemon version v0.17.1`
+fn parse_version(output: &str) -> Option<usize> {
+    let first_line = output.lines().next()?;
+    let version = first_line
+        .split_whitespace()
+        .find(|t| t.starts_with('v') && t[1..].starts_with(|c: char| c.is_ascii_digit()))?;
+    parse_version_string(version).map(|v| v.number())
+}
+
+/// Parses a bitcoind version like `23.0`, `v25.1.0` or `0.21.1`, suffixes of non-release builds
+/// like in `v23.99.0-a1b2c3d4` are ignored
+pub fn parse_version_string(s: &str) -> Option<Version> {
+    let version = s.trim().trim_start_matches('v').split('-').next()?;
+    let mut numbers = version
+        .split('.')
+        .map(|n| n.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    // before 22.0 versions were prefixed by `0.`
+    if numbers.len() > 1 && numbers[0] == 0 {
+        numbers.remove(0);
+    }
+    let number = |i: usize| numbers.get(i).cloned().unwrap_or(0);
+    Some(Version {
+        major: number(0),
+        minor: number(1),
+        patch: number(2),
+    })
+}
+
+/// Returns an error if `args` contain one of the [RESERVED_OPTIONS] or of the `reserved` ones,
+/// negated forms like `-nolisten` included, or select a network other than `network`
+fn check_reserved<'a, I: IntoIterator<Item = &'a str>>(
+    args: I,
+    reserved: &[&str],
+    network: Network,
+) -> Result<(), Error> {
+    for arg in args {
+        let name = arg
+            .split('=')
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('-');
+        let key = RESERVED_OPTIONS
+            .iter()
+            .chain(reserved)
+            .find(|key| name == **key || name == format!("no{}", key));
+        if let Some(key) = key {
+            return Err(Error::ReservedOption(key.to_string()));
+        }
+        let other_network = NETWORK_ARGS
+            .iter()
+            .find(|n| n.trim_start_matches('-') == name && **n != network.arg());
+        if let Some(conflicts_with) = other_network {
+            return Err(Error::ConflictingOptions {
+                option: "network",
+                conflicts_with,
+            });
+        }
+    }
+    Ok(())
+}
 
 /// Validate the specified arg if there is any unavailable or deprecated one
 pub fn validate_args(args: Vec<&str>) -> anyhow::Result<Vec<&str>> {
@@ -508,107 +3520,2009 @@ pub fn validate_args(args: Vec<&str>) -> anyhow::Result<Vec<&str>> {
         if INVALID_ARGS.iter().any(|x| arg.starts_with(x)) {
             return Err(Error::RpcUserAndPasswordUsed);
         }
-        Ok(())
-    })?;
+        Ok(())
+    })?;
+
+    Ok(args)
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod test {
+    use crate::bitcoincore_rpc::bitcoin::Address;
+    use crate::bitcoincore_rpc::jsonrpc::serde_json::{self, Value};
+    use crate::bitcoincore_rpc::{Auth, Client};
+    use crate::reserve_port;
+    use crate::{
+        check_downloaded_version, check_reserved, exe_path, exe_version, first_unexpected_error,
+        parse_version, parse_version_string, random_salt, rpcauth_arg, taproot_activation_arg,
+        unique_signet_challenge, wait_for, Version,
+    };
+    use crate::{
+        get_available_port, AddressType, BitcoinD, CapturedMessage, Conf, Error, LogEvent,
+        MaxApsFee, Network, NodeStatus, Output, RpcAuth, LOCAL_IP, MIN_PRUNE_TARGET_MIB, P2P,
+    };
+    use bitcoincore_rpc::RpcApi;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::net::{SocketAddrV4, TcpListener};
+    use std::path::PathBuf;
+    use std::thread;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_local_ip() {
+        assert_eq!("127.0.0.1", format!("{}", LOCAL_IP));
+        let port = get_available_port().unwrap();
+        let socket = SocketAddrV4::new(LOCAL_IP, port);
+        assert_eq!(format!("127.0.0.1:{}", port), format!("{}", socket));
+    }
+
+    #[test]
+    fn test_reserve_port() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| thread::spawn(|| (0..50).map(|_| reserve_port().unwrap()).collect::<Vec<_>>()))
+            .collect();
+        let listeners: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        let ports: HashSet<_> = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().port())
+            .collect();
+        assert_eq!(ports.len(), listeners.len());
+
+        // a reserved port can't be bound by others until released
+        let listener = reserve_port().unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(TcpListener::bind((LOCAL_IP, port)).is_err());
+        drop(listener);
+        assert!(TcpListener::bind((LOCAL_IP, port)).is_ok());
+    }
+
+    #[test]
+    fn test_option_args() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        assert!(Conf::default().option_args().is_empty());
+
+        let mut conf = Conf::default();
+        conf.rpc_threads = Some(8);
+        conf.rpc_work_queue = Some(64);
+        conf.proxy = Some(SocketAddrV4::new(LOCAL_IP, 9050));
+        conf.limit_ancestor_size = Some(101);
+        conf.limit_descendant_size = Some(102);
+        conf.shrink_debug_file = Some(false);
+        conf.db_batch_size = Some(1 << 20);
+        conf.max_sig_cache_size = Some(4);
+        conf.par = Some(-1);
+        conf.whitelist_relay = Some(true);
+        conf.whitelist_force_relay = Some(false);
+        conf.max_send_buffer_kb = Some(10);
+        conf.max_receive_buffer_kb = Some(20);
+        conf.persist_mempool_v1 = Some(true);
+        conf.address_type = Some(AddressType::P2shSegwit);
+        conf.change_type = Some(AddressType::Bech32m);
+        conf.max_aps_fee = Some(MaxApsFee::UpTo(Amount::from_sat(1_000)));
+        conf.dust_relay_fee = Some(Amount::from_sat(100_000));
+        conf.keypool = Some(1_000);
+        conf.max_mempool_mb = Some(5);
+        conf.read_only = true;
+        conf.signet_challenge = Some("51".to_string());
+        conf.set("maxorphantx", 10)
+            .set("-blockfilterindex", "basic");
+        conf.max_time_adjustment = Some(Duration::from_secs(0));
+        conf.block_filter_index = true;
+        conf.prune = Some(550);
+        assert_eq!(
+            conf.option_args(),
+            vec![
+                "-rpcthreads=8",
+                "-rpcworkqueue=64",
+                "-proxy=127.0.0.1:9050",
+                "-limitancestorsize=101",
+                "-limitdescendantsize=102",
+                "-shrinkdebugfile=0",
+                "-dbbatchsize=1048576",
+                "-maxsigcachesize=4",
+                "-par=-1",
+                "-whitelistrelay=1",
+                "-whitelistforcerelay=0",
+                "-maxsendbuffer=10",
+                "-maxreceivebuffer=20",
+                "-persistmempoolv1=1",
+                "-addresstype=p2sh-segwit",
+                "-changetype=bech32m",
+                "-maxapsfee=0.00001",
+                "-dustrelayfee=0.001",
+                "-keypool=1000",
+                "-maxmempool=5",
+                "-disablewallet=1",
+                "-signetchallenge=51",
+                "-maxtimeadjustment=0",
+                "-blockfilterindex=1",
+                "-peerblockfilters=1",
+                "-prune=550",
+                "-maxorphantx=10",
+                "-blockfilterindex=basic",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_args() {
+        assert_eq!(Conf::default().default_args(), vec!["-fallbackfee=0.0001"]);
+
+        let mut conf = Conf::default();
+        conf.wallet = None;
+        assert!(conf.default_args().is_empty());
+        let mut conf = Conf::default();
+        conf.read_only = true;
+        assert!(conf.default_args().is_empty());
+        let mut conf = Conf::default();
+        conf.fallback_fee = None;
+        assert!(conf.default_args().is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_launch_envs() {
+        use crate::{Launch, StartupPolicy};
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("bitcoind");
+        fs::write(&script, "#!/bin/sh\necho \"$BITCOIND_TEST_ENV $HOME\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let launch = Launch {
+            exe: script.into_os_string(),
+            args: vec![],
+            output: Output::File,
+            output_file: dir.path().join("output"),
+            log_tee: None,
+            startup: StartupPolicy::default(),
+            wallet: None,
+            descriptors: None,
+            envs: vec![("BITCOIND_TEST_ENV".into(), "set".into())],
+            conf_file: None,
+            network: Network::Regtest,
+            reserved: vec![],
+        };
+        launch.spawn(0, false, &[]).unwrap().wait().unwrap();
+        let output = fs::read_to_string(dir.path().join("output")).unwrap();
+        // the parent environment is still inherited
+        let home = std::env::var("HOME").unwrap_or_default();
+        assert_eq!(output, format!("set {}\n", home));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_launch_conf_file() {
+        use crate::{Launch, StartupPolicy};
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("bitcoind");
+        fs::write(&script, "#!/bin/sh\necho \"$@\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let conf_file = dir.path().join("bitcoin.conf");
+        let launch = Launch {
+            exe: script.into_os_string(),
+            args: vec![
+                "-datadir=/tmp/node".to_string(),
+                "-signet".to_string(),
+                "-port=18444".to_string(),
+                "-listen=1".to_string(),
+                "-txindex".to_string(),
+                "-blocknotify=echo %s >> blocks".to_string(),
+            ],
+            output: Output::File,
+            output_file: dir.path().join("output"),
+            log_tee: None,
+            startup: StartupPolicy::default(),
+            wallet: None,
+            descriptors: None,
+            envs: vec![],
+            conf_file: Some(conf_file.clone()),
+            network: Network::Signet,
+            reserved: vec![],
+        };
+        launch
+            .spawn(18443, false, &["-reindex"])
+            .unwrap()
+            .wait()
+            .unwrap();
+        let output = fs::read_to_string(dir.path().join("output")).unwrap();
+        let expected = format!(
+            "-datadir=/tmp/node -conf={} -reindex\n",
+            conf_file.display()
+        );
+        assert_eq!(output, expected);
+        let expected = "signet=1\n\n[signet]\nrpcport=18443\nport=18444\nlisten=1\ntxindex=1\n\
+            blocknotify=echo %s >> blocks\n";
+        assert_eq!(fs::read_to_string(&conf_file).unwrap(), expected);
+
+        // the file is rewritten with the port of the new launch
+        launch.spawn(18445, false, &[]).unwrap().wait().unwrap();
+        let content = fs::read_to_string(&conf_file).unwrap();
+        assert!(content.contains("rpcport=18445\n"));
+        assert!(!content.contains("rpcport=18443"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_launch_no_pipes_left() {
+        use crate::{Launch, StartupPolicy};
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("bitcoind");
+        fs::write(&script, "#!/bin/sh\necho out\necho err >&2\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        for &(output, tee) in &[
+            (Output::Null, false),
+            (Output::File, false),
+            (Output::Null, true),
+        ] {
+            let launch = Launch {
+                exe: script.clone().into_os_string(),
+                args: vec![],
+                output,
+                output_file: dir.path().join("output"),
+                log_tee: Some(dir.path().join("tee")).filter(|_| tee),
+                startup: StartupPolicy::default(),
+                wallet: None,
+                descriptors: None,
+                envs: vec![],
+                conf_file: None,
+                network: Network::Regtest,
+                reserved: vec![],
+            };
+            let mut process = launch.spawn(0, false, &[]).unwrap();
+            assert!(process.stdout.is_none(), "stdout is taken by the tee");
+            assert!(process.stderr.is_none());
+            process.wait().unwrap();
+        }
+        let output = fs::read_to_string(dir.path().join("output")).unwrap();
+        assert_eq!(output, "out\nerr\n");
+    }
+
+    #[test]
+    fn test_resolve_exe() {
+        use crate::{exe_from_env, resolve_exe};
+        use std::ffi::OsStr;
+        let dir = TempDir::new().unwrap();
+        let exe = dir
+            .path()
+            .join(format!("bitcoind{}", std::env::consts::EXE_SUFFIX));
+        fs::write(&exe, "").unwrap();
+        assert_eq!(resolve_exe(exe.as_os_str()).unwrap(), exe.as_os_str());
+        // the suffix is appended on Windows only
+        let without_suffix = dir.path().join("bitcoind");
+        assert_eq!(
+            resolve_exe(without_suffix.as_os_str()).unwrap(),
+            exe.as_os_str()
+        );
+
+        let missing = dir.path().join("missing");
+        let err = resolve_exe(missing.as_os_str()).unwrap_err();
+        assert!(matches!(err, Error::ExeNotFound(path) if path == missing));
+        let err = resolve_exe(OsStr::new("surely-not-an-executable-in-path")).unwrap_err();
+        assert!(matches!(err, Error::ExeNotFound(_)));
+
+        if let Ok(from_env) = exe_from_env() {
+            assert!(from_env.is_file());
+        }
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        use crate::copy_dir;
+        let from = TempDir::new().unwrap();
+        let regtest = from.path().join("regtest");
+        fs::create_dir_all(regtest.join("blocks")).unwrap();
+        for file in &[".lock", ".cookie", "bitcoind.pid", "blocks/blk00000.dat"] {
+            fs::write(regtest.join(file), "").unwrap();
+        }
+        fs::write(from.path().join("settings.json"), "{}").unwrap();
+
+        let to = TempDir::new().unwrap();
+        copy_dir(from.path(), to.path()).unwrap();
+        assert!(to.path().join("settings.json").is_file());
+        assert!(to.path().join("regtest/blocks/blk00000.dat").is_file());
+        for skipped in &[".lock", ".cookie", "bitcoind.pid"] {
+            assert!(!to.path().join("regtest").join(skipped).exists());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_connect_params_serde() {
+        use crate::ConnectParams;
+        let params = ConnectParams {
+            cookie_file: PathBuf::from("/tmp/regtest/.cookie"),
+            rpc_socket: SocketAddrV4::new(LOCAL_IP, 18443),
+            p2p_socket: Some(SocketAddrV4::new(LOCAL_IP, 18444)),
+            rpc_auth: RpcAuth::UserPass("user".to_string(), "pass".to_string()),
+            zmq_rawblock: None,
+            zmq_rawtx: None,
+            rpc_proxy: None,
+        };
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(json["cookie_file"], "/tmp/regtest/.cookie");
+        assert_eq!(json["rpc_socket"], "127.0.0.1:18443");
+        assert_eq!(json["p2p_socket"], "127.0.0.1:18444");
+        let back: ConnectParams = serde_json::from_value(json).unwrap();
+        assert_eq!(back, params);
+
+        let p2p = P2P::Connect(SocketAddrV4::new(LOCAL_IP, 18444), true);
+        let json = serde_json::to_string(&p2p).unwrap();
+        assert_eq!(json, r#"{"Connect":["127.0.0.1:18444",true]}"#);
+        assert_eq!(serde_json::from_str::<P2P>(&json).unwrap(), p2p);
+    }
+
+    #[test]
+    fn test_fully_synced() {
+        use crate::fully_synced;
+        let info = |blocks: u64, headers: u64, progress: f64| {
+            serde_json::json!({
+                "blocks": blocks,
+                "headers": headers,
+                "verificationprogress": progress,
+                "initialblockdownload": true,
+            })
+        };
+        assert!(fully_synced(&info(0, 0, 1.0)));
+        assert!(fully_synced(&info(100, 100, 0.99999)));
+        assert!(!fully_synced(&info(90, 100, 1.0)));
+        assert!(!fully_synced(&info(100, 100, 0.5)));
+        assert!(!fully_synced(&serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_wallet_already_exists() {
+        use crate::bitcoincore_rpc::jsonrpc::error::RpcError;
+        use crate::wallet_already_exists;
+        let rpc_error = |code: i32, message: &str| {
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(RpcError {
+                code,
+                message: message.to_string(),
+                data: None,
+            }))
+        };
+        assert!(wallet_already_exists(&rpc_error(
+            -4,
+            "Wallet default already exists."
+        )));
+        assert!(wallet_already_exists(&rpc_error(
+            -4,
+            "Failed to create database path '/tmp/regtest/wallets/default'. Database already exists."
+        )));
+        assert!(!wallet_already_exists(&rpc_error(
+            -4,
+            "Wallet file verification failed."
+        )));
+        assert!(!wallet_already_exists(
+            &bitcoincore_rpc::Error::ReturnedError("already exists".to_string())
+        ));
+    }
+
+    #[test]
+    fn test_next_poll_interval() {
+        use crate::StartupPolicy;
+        use std::time::Duration;
+        let startup = StartupPolicy::default();
+        let mut interval = startup.poll_interval;
+        let mut intervals = vec![];
+        for _ in 0..6 {
+            intervals.push(interval.as_millis());
+            interval = startup.next_poll_interval(interval);
+        }
+        assert_eq!(intervals, vec![50, 100, 200, 400, 500, 500]);
+        assert_eq!(
+            startup.next_poll_interval(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        use std::error::Error as _;
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let err = Error::from(io);
+        assert_eq!(err.to_string(), "io::Error: no such file");
+        assert_eq!(err.source().unwrap().to_string(), "no such file");
+        assert!(Error::NoEnvVar.source().is_none());
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(err);
+        assert!(boxed.to_string().contains("no such file"));
+    }
+
+    #[test]
+    fn test_check_downloaded_version() {
+        check_downloaded_version("23.0", "23.0").unwrap();
+        assert!(matches!(
+            check_downloaded_version("22.0", "23.0"),
+            Err(Error::VersionNotDownloaded { .. })
+        ));
+        let err = check_downloaded_version("26.0", "23.0").unwrap_err();
+        assert!(matches!(err, Error::UnknownVersion(_)));
+        assert!(err.to_string().contains("0.17.1"));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("Bitcoin Core version v23.0.0\nCopyright (C)"),
+            Some(230_000)
+        );
+        assert_eq!(parse_version("Bitcoin Core version v25.1.0"), Some(250_100));
+        assert_eq!(parse_version("Bitcoin Core version v0.21.1"), Some(210_100));
+        assert_eq!(
+            parse_version("Bitcoin Core version v0.19.0.1"),
+            Some(190_001)
+        );
+        assert_eq!(
+            parse_version("Bitcoin Core Daemon version v0.17.1"),
+            Some(170_100)
+        );
+        assert_eq!(
+            parse_version("Bitcoin Core version v23.99.0-a1b2c3d4"),
+            Some(239_900)
+        );
+        assert_eq!(parse_version("Bitcoin Core version"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn test_parse_version_string() {
+        let version = |major, minor, patch| Version {
+            major,
+            minor,
+            patch,
+        };
+        assert_eq!(parse_version_string("23.0"), Some(version(23, 0, 0)));
+        assert_eq!(parse_version_string("v25.1.0"), Some(version(25, 1, 0)));
+        assert_eq!(parse_version_string("0.21.1"), Some(version(21, 1, 0)));
+        assert_eq!(parse_version_string("0.19.0.1"), Some(version(19, 0, 1)));
+        assert_eq!(
+            parse_version_string("v23.99.0-a1b2c3d4"),
+            Some(version(23, 99, 0))
+        );
+        assert_eq!(parse_version_string("version"), None);
+        assert_eq!(parse_version_string(""), None);
+
+        assert!(version(0, 21, 1) < version(22, 0, 0));
+        assert!(version(23, 0, 0) < version(23, 1, 0));
+        assert_eq!(Version::from_number(190_001), version(19, 0, 1));
+        assert_eq!(version(21, 1, 0).number(), 210_100);
+    }
+
+    #[test]
+    fn test_accept_stale_fee_estimates() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.accept_stale_fee_estimates = true;
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 250_000 {
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion { .. })
+            ));
+        } else {
+            result.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_persist_mempool_v1() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.persist_mempool_v1 = Some(true);
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 270_000 {
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion {
+                    option: "-persistmempoolv1",
+                    ..
+                })
+            ));
+        } else {
+            result.unwrap();
+        }
+    }
+
+    #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
+    #[test]
+    fn test_wallet_descriptors() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.wallet_descriptors = Some(true);
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 210_000 {
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion {
+                    option: "wallet_descriptors",
+                    ..
+                })
+            ));
+        } else {
+            let bitcoind = result.unwrap();
+            let info: Value = bitcoind.client.call("getwalletinfo", &[]).unwrap();
+            assert_eq!(info["descriptors"], true);
+            let other = bitcoind.create_wallet("other").unwrap();
+            let info: Value = other.call("getwalletinfo", &[]).unwrap();
+            assert_eq!(info["descriptors"], true);
+        }
+    }
+
+    #[test]
+    fn test_max_aps_fee() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        assert_eq!(MaxApsFee::Disabled.arg_value(), "-1");
+        assert_eq!(MaxApsFee::Always.arg_value(), "0");
+        assert_eq!(
+            MaxApsFee::UpTo(Amount::from_sat(1_000)).arg_value(),
+            "0.00001"
+        );
+
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.max_aps_fee = Some(MaxApsFee::Always);
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 210_000 {
+            assert!(result.is_err());
+        } else {
+            result.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dust_relay_fee() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let conf = Conf {
+            dust_relay_fee: Some(Amount::from_sat(100_000)),
+            ..Default::default()
+        };
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let send = |sat| {
+            bitcoind.client.send_to_address(
+                &address,
+                Amount::from_sat(sat),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        // at 100_000 sat/kvB outputs of about 10_000 sat or less are dust
+        assert!(send(5_000).is_err());
+        send(20_000).unwrap();
+    }
+
+    #[test]
+    fn test_taproot_activation_arg() {
+        assert_eq!(
+            taproot_activation_arg(500),
+            "-vbparams=taproot:0:9223372036854775807:500"
+        );
+    }
+
+    #[test]
+    fn test_rpcauth_arg() {
+        // same value of test_bitcoind_rpcauth, generated with rpcauth.py
+        assert_eq!(
+            rpcauth_arg("bitcoind", "bitcoind", "cccd5d7fd36e55c1b8576b8077dc1b83"),
+            "-rpcauth=bitcoind:cccd5d7fd36e55c1b8576b8077dc1b83$60b5676a09f8518dcb4574838fb86f37700cd690d99bd2fdc2ea2bf2ab80ead6"
+        );
+        assert_eq!(random_salt().len(), 32);
+        assert_ne!(random_salt(), random_salt());
+    }
+
+    #[test]
+    fn test_activate_taproot_at() {
+        let exe = init();
+        let conf = Conf {
+            activate_taproot_at: Some(500),
+            ..Default::default()
+        };
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 210_100 {
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion {
+                    option: "activate_taproot_at",
+                    ..
+                })
+            ));
+            return;
+        }
+        let bitcoind = result.unwrap();
+        assert!(!bitcoind.is_softfork_active("taproot").unwrap());
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(576, &address).unwrap();
+        assert!(bitcoind.is_softfork_active("taproot").unwrap());
+    }
+
+    #[test]
+    fn test_signet_challenge_isolation() {
+        let exe = init();
+        if exe_version(exe.as_ref()).unwrap() < 210_000 {
+            // signet is not supported
+            return;
+        }
+        let challenge = unique_signet_challenge();
+        let mut conf = Conf::default();
+        conf.network = Network::Signet;
+        conf.p2p = P2P::Yes;
+        conf.signet_challenge = Some(challenge.clone());
+        let a = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let b = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(a.client.get_blockchain_info().unwrap().chain, "signet");
+        b.connect_to(&a).unwrap();
+
+        conf.signet_challenge = Some(unique_signet_challenge());
+        let c = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert!(c.connect_to(&a).is_err(), "different network magic");
+    }
+
+    #[test]
+    fn test_testnet4() {
+        let exe = init();
+        let mut conf = Conf::default();
+        // don't connect to the real network
+        conf.args = vec!["-connect=0"];
+        conf.network = Network::Testnet4;
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 280_000 {
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion {
+                    option: "-testnet4",
+                    ..
+                })
+            ));
+        } else {
+            let bitcoind = result.unwrap();
+            let info = bitcoind.client.get_blockchain_info().unwrap();
+            assert_eq!(info.chain, "testnet4");
+            assert!(bitcoind.params.cookie_file.ends_with("testnet4/.cookie"));
+        }
+    }
+
+    #[test]
+    fn test_check_reserved() {
+        check_reserved(vec!["-txindex", "-regtest"], &[], Network::Regtest).unwrap();
+        for arg in &["-datadir=/tmp", "--rpcport=1", "-nolisten", "-port"] {
+            let err = check_reserved(vec![*arg], &[], Network::Regtest).unwrap_err();
+            assert!(matches!(err, Error::ReservedOption(_)), "{}", arg);
+        }
+        check_reserved(vec!["-connect=0"], &[], Network::Regtest).unwrap();
+        let err = check_reserved(vec!["-connect=0"], &["connect"], Network::Regtest).unwrap_err();
+        assert!(matches!(err, Error::ReservedOption(key) if key == "connect"));
+        check_reserved(vec!["-signet"], &[], Network::Signet).unwrap();
+        let err = check_reserved(vec!["-signet"], &[], Network::Regtest).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ConflictingOptions {
+                option: "network",
+                conflicts_with: "-signet"
+            }
+        ));
+    }
+
+    #[test]
+    fn test_conf_validate() {
+        Conf::default().validate().unwrap();
+
+        let mut conf = Conf::default();
+        conf.max_connections = Some(0);
+        conf.validate().unwrap();
+        conf.p2p = P2P::Connect(SocketAddrV4::new(LOCAL_IP, 18444), false);
+        conf.validate().unwrap();
+        conf.p2p = P2P::Connect(SocketAddrV4::new(LOCAL_IP, 18444), true);
+        assert!(conf.validate().is_err());
+        conf.p2p = P2P::ConnectMany(vec![SocketAddrV4::new(LOCAL_IP, 18444)], false);
+        conf.validate().unwrap();
+        conf.p2p = P2P::ConnectMany(vec![SocketAddrV4::new(LOCAL_IP, 18444)], true);
+        assert!(conf.validate().is_err());
+        conf.p2p = P2P::Yes;
+        assert!(conf.validate().is_err());
+
+        let mut conf = Conf::default();
+        conf.activate_taproot_at = Some(500);
+        conf.validate().unwrap();
+        conf.network = Network::Signet;
+        assert!(conf.validate().is_err());
+
+        let mut conf = Conf::default();
+        conf.reusedir = Some(PathBuf::from("/tmp/reused"));
+        conf.validate().unwrap();
+        conf.staticdir = Some(PathBuf::from("/tmp/static"));
+        assert!(conf.validate().is_err());
+
+        let mut conf = Conf::default();
+        conf.signet_challenge = Some("51".to_string());
+        assert!(conf.validate().is_err());
+        conf.network = Network::Signet;
+        conf.validate().unwrap();
+
+        let mut conf = Conf::default();
+        conf.set("maxorphantx", 10);
+        conf.validate().unwrap();
+        conf.set("rpcport", 18443);
+        let err = conf.validate().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ReservedOption(key)) if key == "rpcport"
+        ));
+        let mut conf = Conf::default();
+        conf.set("rpcpassword", "secret");
+        let err = conf.validate().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::RpcUserAndPasswordUsed)
+        ));
+
+        let mut conf = Conf::default();
+        conf.args = vec!["-rpcport=18443"];
+        let err = conf.validate().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ReservedOption(key)) if key == "rpcport"
+        ));
+        conf.args = vec!["-nolisten"];
+        assert!(conf.validate().is_err());
+        conf.args = vec!["-portable", "-connect=0"];
+        conf.validate().unwrap();
+        conf.p2p = P2P::Connect(SocketAddrV4::new(LOCAL_IP, 18444), false);
+        assert!(conf.validate().is_err());
+        conf.args = vec!["-bind=127.0.0.1"];
+        conf.validate().unwrap();
+        conf.p2p_bind = Some(SocketAddrV4::new(LOCAL_IP, 18444));
+        assert!(conf.validate().is_err());
+
+        let mut conf = Conf::default();
+        conf.args.push("-regtest");
+        conf.validate().unwrap();
+        conf.args.push("-signet");
+        assert!(conf.validate().is_err());
+        conf.args = vec!["-chain=main"];
+        assert!(conf.validate().is_err());
+        conf.args = vec![];
+        conf.set("chain", "main");
+        assert!(conf.validate().is_err());
+
+        let mut conf = Conf::default();
+        conf.read_only = true;
+        conf.validate().unwrap();
+        conf.bootstrap_addresses = 1;
+        assert!(conf.validate().is_err());
+        conf.read_only = false;
+        conf.validate().unwrap();
+        conf.wallet = None;
+        let err = conf.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`bootstrap_addresses` cannot be used together with `wallet: None`"
+        );
+
+        let mut conf = Conf::default();
+        conf.prune = Some(1);
+        conf.validate().unwrap();
+        conf.prune = Some(MIN_PRUNE_TARGET_MIB);
+        conf.validate().unwrap();
+        conf.prune = Some(100);
+        let err = conf.validate().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidPruneTarget(100))
+        ));
+        conf.prune = Some(1);
+        conf.args.push("-txindex");
+        assert!(conf.validate().is_err());
+        conf.args = vec!["-txindex=0"];
+        conf.validate().unwrap();
+        conf.set("txindex", 1);
+        assert!(conf.validate().is_err());
+    }
+
+    #[test]
+    fn test_network_magic() {
+        let mut conf = Conf::default();
+        assert_eq!(conf.network_magic(), Some([0xfa, 0xbf, 0xb5, 0xda]));
+        conf.network = Network::Signet;
+        assert_eq!(conf.network_magic(), Some([0x0a, 0x03, 0xcf, 0x40]));
+        conf.signet_challenge = Some(unique_signet_challenge());
+        let magic = conf.network_magic().unwrap();
+        assert_ne!(magic, [0x0a, 0x03, 0xcf, 0x40]);
+        conf.signet_challenge = Some(unique_signet_challenge());
+        assert_ne!(conf.network_magic().unwrap(), magic);
+        conf.signet_challenge = Some("not hex".to_string());
+        assert_eq!(conf.network_magic(), None);
+    }
+
+    #[test]
+    fn test_bitcoind() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let info = bitcoind.client.get_blockchain_info().unwrap();
+        assert_eq!(0, info.blocks);
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let _ = bitcoind.client.generate_to_address(1, &address).unwrap();
+        let info = bitcoind.client.get_blockchain_info().unwrap();
+        assert_eq!(1, info.blocks);
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert_eq!(bitcoind.sync_progress().unwrap(), (0, 0));
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(2, &address).unwrap();
+        assert_eq!(bitcoind.sync_progress().unwrap(), (2, 2));
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(12, &address).unwrap();
+        let tip = bitcoind.client.get_best_block_hash().unwrap();
+        let header = bitcoind.client.get_block_header_info(&tip).unwrap();
+        let mtp = bitcoind.median_time_past().unwrap();
+        assert_eq!(Some(mtp as usize), header.median_time);
+        assert!(mtp <= header.time as u64);
+    }
+
+    #[test]
+    fn test_mempool_min_fee() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        // with an empty mempool it's the relay floor
+        let relay_fee = bitcoind.client.get_network_info().unwrap().relay_fee;
+        assert_eq!(bitcoind.mempool_min_fee().unwrap(), relay_fee);
+    }
+
+    #[test]
+    fn test_fill_mempool() {
+        let exe = init();
+        let conf = Conf {
+            max_mempool_mb: Some(5),
+            ..Default::default()
+        };
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert!(bitcoind.fill_mempool(1.5).is_err());
+
+        let txids = bitcoind.fill_mempool(0.1).unwrap();
+        assert!(!txids.is_empty());
+        let info: Value = bitcoind.client.call("getmempoolinfo", &[]).unwrap();
+        let usage = info["usage"].as_u64().unwrap();
+        assert!(usage >= 500_000);
+        let mempool = bitcoind.client.get_raw_mempool().unwrap();
+        assert!(txids.iter().all(|txid| mempool.contains(txid)));
+    }
+
+    #[test]
+    fn test_chain_tx_stats() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let hashes = bitcoind.client.generate_to_address(10, &address).unwrap();
+
+        let stats = bitcoind.chain_tx_stats(Some(5)).unwrap();
+        // genesis and one coinbase per block
+        assert_eq!(stats.tx_count, 11);
+        assert_eq!(stats.window_block_count, 5);
+        assert_eq!(stats.window_tx_count, Some(5));
+        assert_eq!(&stats.window_final_block_hash, hashes.last().unwrap());
+        bitcoind.chain_tx_stats(None).unwrap();
+    }
+
+    #[test]
+    fn test_node_status_parse() {
+        let json = r#"{"chain":"regtest","blocks":1,"headers":1,"bestblockhash":"0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206","difficulty":4.656542373906925e-10,"time":1296688602,"mediantime":1296688602,"verificationprogress":1,"initialblockdownload":false,"chainwork":"0000000000000000000000000000000000000000000000000000000000000002","size_on_disk":293,"pruned":false,"warnings":""}"#;
+        let status = NodeStatus::from_value(serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(status.chain, "regtest");
+        assert_eq!(status.blocks, 1);
+        assert_eq!(status.headers, 1);
+        assert_eq!(
+            status.best_block_hash.to_string(),
+            "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"
+        );
+        assert_eq!(status.verification_progress, 1.0);
+        assert!(!status.ibd);
+        assert_eq!(status.size_on_disk, 293);
+        assert!(!status.pruned);
+    }
+
+    #[test]
+    fn test_version() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(&exe).unwrap();
+        let version = bitcoind.version().unwrap();
+        assert_eq!(version.number(), exe_version(exe.as_ref()).unwrap());
+    }
+
+    #[test]
+    fn test_status() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let status = bitcoind.status().unwrap();
+        assert_eq!(status.chain, "regtest");
+        assert_eq!(status.blocks, 0);
+        // a node without blocks is in initial block download
+        assert!(status.ibd);
+        let hashes = bitcoind.generate(2).unwrap();
+        let status = bitcoind.status().unwrap();
+        assert_eq!(status.blocks, 2);
+        assert_eq!(status.headers, 2);
+        assert_eq!(status.best_block_hash, hashes[1]);
+        assert!(!status.ibd);
+        assert!(!status.pruned);
+    }
+
+    #[test]
+    fn test_total_supply() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert_eq!(bitcoind.total_supply().unwrap(), Amount::ZERO);
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let expected = Amount::from_btc(50.0).unwrap() * 101;
+        assert_eq!(bitcoind.total_supply().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_wait_for_balance() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let one_second = Duration::from_secs(1);
+        let fifty = Amount::from_btc(50.0).unwrap();
+        let err = bitcoind.wait_for_balance(fifty, one_second).unwrap_err();
+        assert!(matches!(err, Error::Timeout(_)));
+
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let balance = bitcoind.wait_for_balance(fifty, one_second).unwrap();
+        assert!(balance >= fifty);
+    }
+
+    #[test]
+    fn test_address_type() {
+        use bitcoincore_rpc::bitcoin::AddressType as Type;
+        let exe = init();
+        let conf = Conf {
+            address_type: Some(AddressType::Legacy),
+            ..Default::default()
+        };
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        assert_eq!(address.address_type(), Some(Type::P2pkh));
+
+        for (address_type, expected) in &[
+            (AddressType::Legacy, Type::P2pkh),
+            (AddressType::P2shSegwit, Type::P2sh),
+            (AddressType::Bech32, Type::P2wpkh),
+        ] {
+            let address = bitcoind.get_new_address_of_type(*address_type).unwrap();
+            assert_eq!(address.address_type(), Some(*expected));
+        }
+        let bech32m = bitcoind.get_new_address_of_type(AddressType::Bech32m);
+        let version = exe_version(exe.as_ref()).unwrap();
+        if version < 220_000 {
+            assert!(matches!(
+                bech32m.unwrap_err().downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion { .. })
+            ));
+        } else if version >= 230_000 {
+            // descriptor wallets are the default since 23.0
+            assert_eq!(bech32m.unwrap().address_type(), Some(Type::P2tr));
+        }
+    }
+
+    #[test]
+    fn test_get_raw_change_address() {
+        use bitcoincore_rpc::bitcoin::AddressType as Type;
+        let exe = init();
+        let conf = Conf {
+            change_type: Some(AddressType::Legacy),
+            ..Default::default()
+        };
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let change = bitcoind.get_raw_change_address().unwrap();
+        assert_eq!(change.address_type(), Some(Type::P2pkh));
+    }
+
+    #[test]
+    fn test_next_address() {
+        let exe = init();
+        let conf = Conf {
+            keypool: Some(10),
+            bootstrap_addresses: 3,
+            ..Default::default()
+        };
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let addresses: Vec<_> = (0..5).map(|_| bitcoind.next_address().unwrap()).collect();
+        for (i, address) in addresses.iter().enumerate() {
+            assert!(!addresses[i + 1..].contains(address), "addresses are fresh");
+        }
+    }
+
+    #[test]
+    fn test_wait_for_tx_evicted() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let one_second = Duration::from_secs(1);
+        let err = bitcoind.wait_for_tx_evicted(&txid, one_second).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Timeout(_))
+        ));
+
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        bitcoind.wait_for_tx_evicted(&txid, one_second).unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_block_height_and_mempool_tx() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        let exe = init();
+        let (a, b) = BitcoinD::partitioned_pair(&exe, 101, 0).unwrap();
+        let one_second = Duration::from_secs(1);
+        let err = b.wait_for_block_height(101, one_second).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Timeout(_))
+        ));
+        b.connect_to(&a).unwrap();
+        b.wait_for_block_height(101, Duration::from_secs(10))
+            .unwrap();
+
+        let address = b.client.get_new_address(None, None).unwrap();
+        let txid = a
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        b.wait_for_mempool_tx(&txid, Duration::from_secs(30))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tx_fee_rate() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let block_hash = bitcoind.client.generate_to_address(101, &address).unwrap()[0];
+        // received by the wallet, so the fee is unknown
+        let coinbase_txid = bitcoind.coinbase_tx(&block_hash).unwrap().txid();
+        let err = bitcoind.tx_fee_rate(&coinbase_txid).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::UnknownFee(_))
+        ));
+
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let mempool_rate = bitcoind.tx_fee_rate(&txid).unwrap();
+        assert!(mempool_rate > 0.0);
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        let wallet_rate = bitcoind.tx_fee_rate(&txid).unwrap();
+        assert!((mempool_rate - wallet_rate).abs() < 1e-9);
+    }
+
+    #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
+    #[test]
+    fn test_confirmations() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.args.push("-txindex");
+        let bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(bitcoind.confirmations(&txid).unwrap(), 0);
+        bitcoind.client.generate_to_address(2, &address).unwrap();
+        assert_eq!(bitcoind.confirmations(&txid).unwrap(), 2);
+
+        // not in the wallet, found through the txindex
+        let other = bitcoind.create_wallet("other").unwrap();
+        let other_address = other.get_new_address(None, None).unwrap();
+        let block_hash = bitcoind
+            .client
+            .generate_to_address(1, &other_address)
+            .unwrap()[0];
+        let coinbase_txid = bitcoind.coinbase_tx(&block_hash).unwrap().txid();
+        assert_eq!(bitcoind.confirmations(&coinbase_txid).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_zmq() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(&exe).unwrap();
+        assert!(bitcoind.params.zmq_rawblock.is_none());
+        assert!(bitcoind.params.zmq_rawtx.is_none());
+
+        let mut conf = Conf::default();
+        conf.zmq = true;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let rawblock = bitcoind.params.zmq_rawblock.unwrap();
+        let rawtx = bitcoind.params.zmq_rawtx.unwrap();
+        assert_ne!(rawblock, rawtx);
+        let notifications: Value = bitcoind.client.call("getzmqnotifications", &[]).unwrap();
+        let addresses: Vec<_> = notifications
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| (n["type"].as_str().unwrap(), n["address"].as_str().unwrap()))
+            .collect();
+        assert!(addresses.contains(&("pubrawblock", &*format!("tcp://{}", rawblock))));
+        assert!(addresses.contains(&("pubrawtx", &*format!("tcp://{}", rawtx))));
+    }
+
+    #[test]
+    fn test_time_submit_block() {
+        use std::time::Duration;
+        let exe = init();
+        let a = BitcoinD::new(&exe).unwrap();
+        let b = BitcoinD::new(&exe).unwrap();
+        let address = a.client.get_new_address(None, None).unwrap();
+        let block_hash = a.client.generate_to_address(1, &address).unwrap()[0];
+        let block_hex = a.client.get_block_hex(&block_hash).unwrap();
+
+        let elapsed = b.time_submit_block(&block_hex).unwrap();
+        assert!(elapsed > Duration::from_secs(0));
+        assert_eq!(b.client.get_best_block_hash().unwrap(), block_hash);
+
+        let err = b.time_submit_block(&block_hex).unwrap_err();
+        assert!(matches!(err, Error::BlockRejected(reason) if reason == "duplicate"));
+    }
+
+    #[test]
+    fn test_generate_and_fund_wallet() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let hashes = bitcoind.generate(2).unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(bitcoind.client.get_best_block_hash().unwrap(), hashes[1]);
+        assert_eq!(
+            bitcoind.client.get_balance(None, None).unwrap(),
+            Amount::ZERO
+        );
+
+        // the coinbases of the first 3 blocks are mature
+        let balance = bitcoind.fund_wallet().unwrap();
+        assert_eq!(balance, Amount::from_btc(150.0).unwrap());
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 103);
+    }
+
+    #[test]
+    fn test_wallet_utxos() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert!(bitcoind.wallet_utxos().unwrap().is_empty());
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(102, &address).unwrap();
+        let utxos = bitcoind.wallet_utxos().unwrap();
+        // coinbase outputs are returned by listunspent only when mature
+        assert_eq!(utxos.len(), 2);
+        assert!(utxos.iter().all(|(_, _, spendable)| *spendable));
+
+        let (locked, amount, _) = utxos[0];
+        assert!(bitcoind.client.lock_unspent(&[locked]).unwrap());
+        let utxos = bitcoind.wallet_utxos().unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert!(utxos.contains(&(locked, amount, false)));
+        assert_eq!(utxos.iter().filter(|(_, _, s)| *s).count(), 1);
+    }
+
+    #[test]
+    fn test_get_tx_out() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let tx = bitcoind.client.get_raw_transaction(&txid, None).unwrap();
+        let spent = tx.input[0].previous_output;
+
+        // created by a mempool transaction
+        let out = bitcoind.get_tx_out(&txid, 0, true).unwrap().unwrap();
+        assert_eq!(out.confirmations, 0);
+        assert!(bitcoind.get_tx_out(&txid, 0, false).unwrap().is_none());
+
+        // spent by a mempool transaction
+        assert!(bitcoind
+            .get_tx_out(&spent.txid, spent.vout, true)
+            .unwrap()
+            .is_none());
+        assert!(bitcoind
+            .get_tx_out(&spent.txid, spent.vout, false)
+            .unwrap()
+            .is_some());
+
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        let out = bitcoind.get_tx_out(&txid, 0, false).unwrap().unwrap();
+        assert_eq!(out.confirmations, 1);
+        assert!(bitcoind
+            .get_tx_out(&spent.txid, spent.vout, false)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_list_transactions() {
+        use bitcoincore_rpc::json::GetTransactionResultDetailCategory;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert!(bitcoind.list_transactions(10).unwrap().is_empty());
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+
+        let transactions = bitcoind.list_transactions(5).unwrap();
+        assert_eq!(transactions.len(), 5);
+        assert!(transactions
+            .iter()
+            .all(|t| t.detail.category == GetTransactionResultDetailCategory::Immature));
+    }
+
+    #[test]
+    fn test_is_softfork_active() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert!(bitcoind.is_softfork_active("segwit").unwrap());
+        let err = bitcoind.is_softfork_active("unknown").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::UnknownSoftfork(_))
+        ));
+    }
+
+    #[test]
+    fn test_uptime() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert!(bitcoind.uptime().unwrap() < std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    #[cfg(any(feature = "0_21_0", feature = "0_21_1"))]
+    fn test_getindexinfo() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.args.push("-txindex");
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert!(
+            bitcoind.client.version().unwrap() >= 210_000,
+            "getindexinfo requires bitcoin >0.21"
+        );
+        let info: std::collections::HashMap<String, bitcoincore_rpc::jsonrpc::serde_json::Value> =
+            bitcoind.client.call("getindexinfo", &[]).unwrap();
+        assert!(info.contains_key("txindex"));
+        assert!(bitcoind.client.version().unwrap() >= 210_000);
+
+        bitcoind.generate(10).unwrap();
+        bitcoind
+            .wait_for_index_sync(std::time::Duration::from_secs(10))
+            .unwrap();
+        let info: std::collections::HashMap<String, Value> =
+            bitcoind.client.call("getindexinfo", &[]).unwrap();
+        assert_eq!(info["txindex"]["best_block_height"], 10);
+    }
+
+    #[test]
+    fn test_startup_timeout() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.startup.timeout = Some(std::time::Duration::from_millis(1));
+        conf.startup.max_port_retries = 0;
+        let err = BitcoinD::with_conf(exe, &conf).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::StartupTimeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_p2p() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(peers_connected(&bitcoind.client), 0);
+        let mut other_conf = Conf::default();
+        other_conf.p2p = bitcoind.p2p_connect(false).unwrap();
+
+        let other_bitcoind = BitcoinD::with_conf(&exe, &other_conf).unwrap();
+        assert_eq!(peers_connected(&bitcoind.client), 1);
+        assert_eq!(peers_connected(&other_bitcoind.client), 1);
+    }
+
+    #[test]
+    fn test_p2p_connect_many() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+        let spoke_a = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let spoke_b = BitcoinD::with_conf(&exe, &conf).unwrap();
+
+        let mut hub_conf = Conf::default();
+        hub_conf.p2p = P2P::ConnectMany(
+            vec![
+                spoke_a.params.p2p_socket.unwrap(),
+                spoke_b.params.p2p_socket.unwrap(),
+            ],
+            false,
+        );
+        let hub = BitcoinD::with_conf(&exe, &hub_conf).unwrap();
+        hub.wait_for_connections(2, std::time::Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(peers_connected(&hub.client), 2);
+        assert_eq!(peers_connected(&spoke_a.client), 1);
+        assert_eq!(peers_connected(&spoke_b.client), 1);
+    }
+
+    #[test]
+    fn test_p2p_bind() {
+        let exe = init();
+        let bind = SocketAddrV4::new(LOCAL_IP, get_available_port().unwrap());
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+        conf.p2p_bind = Some(bind);
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(bitcoind.params.p2p_socket, Some(bind));
+
+        let mut other_conf = Conf::default();
+        other_conf.p2p = P2P::Connect(bind, false);
+        let other_bitcoind = BitcoinD::with_conf(&exe, &other_conf).unwrap();
+        assert_eq!(peers_connected(&bitcoind.client), 1);
+        assert_eq!(peers_connected(&other_bitcoind.client), 1);
+    }
+
+    #[test]
+    fn test_max_connections_zero() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+
+        // outbound only node
+        let mut outbound_conf = Conf::default();
+        outbound_conf.p2p = bitcoind.p2p_connect(false).unwrap();
+        outbound_conf.max_connections = Some(0);
+        let outbound = BitcoinD::with_conf(&exe, &outbound_conf).unwrap();
+        assert_eq!(peers_connected(&outbound.client), 1);
+        assert_eq!(peers_connected(&bitcoind.client), 1);
+    }
+
+    #[test]
+    fn test_line() {
+        let exe = init();
+        let nodes = BitcoinD::line(&exe, 3).unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(peers_connected(&nodes[0].client), 1);
+        assert_eq!(peers_connected(&nodes[1].client), 2);
+        assert_eq!(peers_connected(&nodes[2].client), 1);
+    }
+
+    #[test]
+    fn test_mesh() {
+        use std::time::Duration;
+        let exe = init();
+        let nodes = BitcoinD::mesh(&exe, 3).unwrap();
+        for node in &nodes {
+            node.wait_for_connections(2, Duration::from_secs(10))
+                .unwrap();
+        }
 
-    Ok(args)
-}
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+        let a = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let b = BitcoinD::with_conf(&exe, &conf).unwrap();
+        BitcoinD::connect_mesh(&[&a, &b]).unwrap();
+        assert_eq!(peers_connected(&a.client), 1);
+        b.wait_for_connections(1, Duration::from_secs(10)).unwrap();
+        let err = b
+            .wait_for_connections(2, Duration::from_millis(500))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Timeout(_))
+        ));
 
-#[cfg(test)]
-mod test {
-    use crate::bitcoincore_rpc::jsonrpc::serde_json::Value;
-    use crate::bitcoincore_rpc::{Auth, Client};
-    use crate::exe_path;
-    use crate::{get_available_port, BitcoinD, Conf, LOCAL_IP, P2P};
-    use bitcoincore_rpc::RpcApi;
-    use std::net::SocketAddrV4;
-    use tempfile::TempDir;
+        let no_p2p = BitcoinD::new(&exe).unwrap();
+        assert!(matches!(a.add_node(&no_p2p), Err(Error::NoP2pPort)));
+    }
 
     #[test]
-    fn test_local_ip() {
-        assert_eq!("127.0.0.1", format!("{}", LOCAL_IP));
-        let port = get_available_port().unwrap();
-        let socket = SocketAddrV4::new(LOCAL_IP, port);
-        assert_eq!(format!("127.0.0.1:{}", port), format!("{}", socket));
+    #[cfg(feature = "tokio")]
+    fn test_with_conf_async() {
+        // a single thread runtime, the readiness poll must not block it
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let exe = init();
+        let bitcoind = runtime.block_on(BitcoinD::new_async(&exe)).unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 0);
+
+        let mut conf = Conf::default();
+        conf.p2p = P2P::Yes;
+        let bitcoind = runtime
+            .block_on(BitcoinD::with_conf_async(&exe, conf))
+            .unwrap();
+        assert!(bitcoind.params.p2p_socket.is_some());
     }
 
     #[test]
-    fn test_bitcoind() {
+    #[cfg(all(unix, feature = "tokio"))]
+    fn test_with_conf_async_early_exit() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("bitcoind");
+        fs::write(&script, "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let mut conf = Conf::default();
+        conf.startup.max_port_retries = 1;
+        let err = runtime
+            .block_on(BitcoinD::with_conf_async(&script, conf.clone()))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::EarlyExit(_))
+        ));
+        let sync_err = BitcoinD::with_conf(&script, &conf).unwrap_err();
+        assert_eq!(err.to_string(), sync_err.to_string());
+    }
+
+    #[test]
+    fn test_block_notify() {
+        let exe = init();
+        let notify_dir = TempDir::new().unwrap();
+        let notify_file = notify_dir.path().join("blocks");
+        let command = format!("echo %s >> {}", notify_file.display());
+        let mut conf = Conf::default();
+        conf.block_notify = Some(&command);
+        let bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let block_hash = bitcoind.client.generate_to_address(1, &address).unwrap()[0];
+
+        for _ in 0..50 {
+            let content = std::fs::read_to_string(&notify_file).unwrap_or_default();
+            if content.contains(&block_hash.to_string()) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("block hash not notified");
+    }
+
+    #[test]
+    fn test_net_totals() {
+        let exe = init();
+        let nodes = BitcoinD::line(&exe, 2).unwrap();
+        let (received, sent) = nodes[0].net_totals().unwrap();
+        assert!(received > 0);
+        assert!(sent > 0);
+    }
+
+    #[test]
+    fn test_data_persistence() {
+        // Create a Conf with staticdir type
+        let mut conf = Conf::default();
+        let datadir = TempDir::new().unwrap();
+        conf.staticdir = Some(datadir.path().to_path_buf());
+
+        // Start BitcoinD with persistent db config
+        // Generate 101 blocks
+        // Wallet balance should be 50
+        let bitcoind = BitcoinD::with_conf(exe_path().unwrap(), &conf).unwrap();
+        let core_addrs = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind
+            .client
+            .generate_to_address(101, &core_addrs)
+            .unwrap();
+        let wallet_balance_1 = bitcoind.client.get_balance(None, None).unwrap();
+        let best_block_1 = bitcoind.client.get_best_block_hash().unwrap();
+
+        drop(bitcoind);
+
+        // Start a new BitcoinD with the same datadir
+        let bitcoind = BitcoinD::with_conf(exe_path().unwrap(), &conf).unwrap();
+
+        let wallet_balance_2 = bitcoind.client.get_balance(None, None).unwrap();
+        let best_block_2 = bitcoind.client.get_best_block_hash().unwrap();
+
+        // Check node chain data persists
+        assert_eq!(best_block_1, best_block_2);
+
+        // Check the node wallet balance persists
+        assert_eq!(wallet_balance_1, wallet_balance_2);
+    }
+
+    #[test]
+    fn test_log_tee() {
+        use crate::wait_for;
+        use std::time::Duration;
+        let exe = init();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bitcoind.log");
+        let conf = Conf {
+            log_tee: Some(path.clone()),
+            ..Default::default()
+        };
+        let mut bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        bitcoind.stop().unwrap();
+        // the background thread may still be writing
+        wait_for(Duration::from_secs(5), || {
+            let content = fs::read_to_string(&path)?;
+            Ok(if content.contains("Shutdown: done") {
+                Some(())
+            } else {
+                None
+            })
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_output_file() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(&exe).unwrap();
+        assert!(bitcoind.log_contents().is_err());
+
+        let mut conf = Conf::default();
+        conf.output = Output::File;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert!(bitcoind.log_contents().unwrap().contains("Done loading"));
+
+        // the reason of the failure is in the error
+        conf.args.push("-notanoption");
+        conf.startup.max_port_retries = 0;
+        let err = BitcoinD::with_conf(&exe, &conf).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::EarlyExit(_))
+        ));
+        assert!(format!("{:?}", err).contains("-notanoption"));
+    }
+
+    #[test]
+    fn test_restart() {
+        let exe = init();
+        let mut bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let rpc_url = bitcoind.rpc_url();
+        let balance = bitcoind.client.get_balance(None, None).unwrap();
+
+        bitcoind.restart().unwrap();
+        assert_eq!(bitcoind.rpc_url(), rpc_url, "rpc port is preserved");
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 101);
+        assert_eq!(bitcoind.client.get_balance(None, None).unwrap(), balance);
+
+        bitcoind.restart_with_args(&["-reindex"]).unwrap();
+        assert_eq!(bitcoind.rpc_url(), rpc_url);
+        wait_for(std::time::Duration::from_secs(10), || {
+            let count = bitcoind.client.get_block_count()?;
+            Ok(if count == 101 { Some(()) } else { None })
+        })
+        .unwrap();
+        assert_eq!(bitcoind.client.get_balance(None, None).unwrap(), balance);
+        assert!(bitcoind.restart_with_args(&["-rpcuser=u"]).is_err());
+        let err = bitcoind.restart_with_args(&["-rpcport=18443"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ReservedOption(key)) if key == "rpcport"
+        ));
+        // rejected before stopping the node
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 101);
+    }
+
+    #[test]
+    fn test_stop_checked_and_kill() {
+        let exe = init();
+        let mut bitcoind = BitcoinD::new(&exe).unwrap();
+        assert!(bitcoind.is_running());
+        assert!(bitcoind.pid() > 0);
+        bitcoind.stop_checked().unwrap();
+        assert!(bitcoind.client.get_block_count().is_err());
+        assert!(!bitcoind.is_running());
+
+        let mut bitcoind = BitcoinD::new(&exe).unwrap();
+        bitcoind.kill().unwrap();
+        assert!(bitcoind.client.get_block_count().is_err());
+        assert!(!bitcoind.is_running());
+        // killing again is a no-op
+        bitcoind.kill().unwrap();
+    }
+
+    #[test]
+    fn test_keep_datadir_on_panic() {
+        let exe = init();
+        let handle = std::thread::spawn(move || {
+            let mut conf = Conf::default();
+            conf.keep_datadir_on_panic = true;
+            let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+            let workdir = bitcoind.workdir();
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let _moved = bitcoind;
+                panic!("simulated test failure");
+            }));
+            workdir
+        });
+        let workdir = handle.join().unwrap();
+        assert!(workdir.join("regtest").is_dir());
+        fs::remove_dir_all(&workdir).unwrap();
+
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.keep_datadir_on_panic = true;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let workdir = bitcoind.workdir();
+        drop(bitcoind);
+        assert!(!workdir.exists(), "removed when not panicking");
+    }
+
+    #[test]
+    fn test_reusedir() {
+        let exe = init();
+        let dir = TempDir::new().unwrap();
+        let conf = Conf {
+            reusedir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+            // every node starts from an empty chain
+            assert_eq!(0, bitcoind.client.get_block_count().unwrap());
+            let address = bitcoind.client.get_new_address(None, None).unwrap();
+            bitcoind.client.generate_to_address(1, &address).unwrap();
+        }
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn test_captured_message_parse() {
+        use std::time::Duration;
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&1_000_000u64.to_le_bytes());
+        bytes.extend_from_slice(b"verack\0\0\0\0\0\0");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&2_000_000u64.to_le_bytes());
+        bytes.extend_from_slice(b"ping\0\0\0\0\0\0\0\0");
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        bytes.extend_from_slice(&[7u8; 8]);
+        // partially written message
+        bytes.extend_from_slice(&3_000_000u64.to_le_bytes());
+
+        let messages = CapturedMessage::parse_all(&bytes, true);
+        assert_eq!(
+            messages,
+            vec![
+                CapturedMessage {
+                    time: Duration::from_secs(1),
+                    outbound: true,
+                    msg_type: "verack".to_string(),
+                    payload: vec![],
+                },
+                CapturedMessage {
+                    time: Duration::from_secs(2),
+                    outbound: true,
+                    msg_type: "ping".to_string(),
+                    payload: vec![7u8; 8],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_captured_messages() {
+        let exe = init();
+        let conf = Conf {
+            p2p: P2P::Yes,
+            ..Default::default()
+        };
+        let node1 = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let conf = Conf {
+            p2p: node1.p2p_connect(false).unwrap(),
+            capture_messages: true,
+            ..Default::default()
+        };
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 220_000 {
+            assert!(result.is_err());
+            return;
+        }
+        let node2 = result.unwrap();
+        let peers: Vec<Value> = node2.client.call("getpeerinfo", &[]).unwrap();
+        let peer = peers[0]["addr"].as_str().unwrap();
+
+        let messages = node2.captured_messages(peer).unwrap();
+        let version = messages.iter().find(|m| m.msg_type == "version").unwrap();
+        assert!(messages
+            .iter()
+            .any(|m| m.msg_type == "version" && m.outbound != version.outbound));
+        assert!(messages.iter().any(|m| m.msg_type == "verack"));
+    }
+
+    #[test]
+    fn test_get_block_filter() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.block_filter_index = true;
+        let result = BitcoinD::with_conf(&exe, &conf);
+        if exe_version(exe.as_ref()).unwrap() < 190_000 {
+            assert!(matches!(
+                result.unwrap_err().downcast_ref::<Error>(),
+                Some(Error::UnsupportedVersion { .. })
+            ));
+            return;
+        }
+        let bitcoind = result.unwrap();
+        let hash = bitcoind.generate(1).unwrap()[0];
+        let filter = wait_for(std::time::Duration::from_secs(10), || {
+            Ok(bitcoind.get_block_filter(&hash).ok())
+        })
+        .unwrap();
+        assert!(!filter.filter.is_empty());
+    }
+
+    #[test]
+    fn test_prune() {
+        let exe = init();
+        // `-fastprune` is needed to have small block files, otherwise every block is in the file
+        // being written, which is never pruned
+        if exe_version(exe.as_ref()).unwrap() < 220_000 {
+            return;
+        }
+        let mut conf = Conf::default();
+        conf.prune = Some(1);
+        conf.args.push("-fastprune");
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        // the last 288 blocks are always kept and a block file is pruned only when all its blocks
+        // are below the target, with `-fastprune` a file holds a few hundred regtest blocks
+        let hashes = bitcoind.generate(1000).unwrap();
+        let pruned: u64 = bitcoind
+            .client
+            .call("pruneblockchain", &[(1000 - 288).into()])
+            .unwrap();
+        assert!(pruned > 0);
+        assert!(bitcoind.client.get_block(&hashes[0]).is_err());
+        assert!(bitcoind.client.get_block(&hashes[999]).is_ok());
+        assert!(bitcoind.client.get_blockchain_info().unwrap().pruned);
+    }
+
+    #[test]
+    fn test_wait_for_ban() {
+        use std::time::Duration;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        assert!(bitcoind.list_banned().unwrap().is_empty());
+        let one_second = Duration::from_secs(1);
+        let err = bitcoind.wait_for_ban("127.0.0.2", one_second).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Timeout(_))
+        ));
+
+        bitcoind.client.add_ban("127.0.0.2", 60, false).unwrap();
+        let entry = bitcoind.wait_for_ban("127.0.0.2", one_second).unwrap();
+        assert_eq!(entry.address, "127.0.0.2/32");
+        assert_eq!(bitcoind.list_banned().unwrap(), vec![entry]);
+        assert!(bitcoind.wait_for_ban("127.0.0.", one_second).is_err());
+    }
+
+    #[test]
+    fn test_first_unexpected_error() {
+        let lines = || {
+            vec![
+                "2023-03-24T10:15:42Z Bitcoin Core version v24.0.1".to_string(),
+                "2023-03-24T10:15:43Z ERROR: ReadBlockFromDisk: OpenBlockFile failed".to_string(),
+                "2023-03-24T10:15:44Z ERROR: AcceptBlockHeader: prev block not found".to_string(),
+            ]
+            .into_iter()
+        };
+        assert_eq!(
+            first_unexpected_error(lines(), &[]).unwrap(),
+            "2023-03-24T10:15:43Z ERROR: ReadBlockFromDisk: OpenBlockFile failed"
+        );
+        assert_eq!(
+            first_unexpected_error(lines(), &["ReadBlockFromDisk"]).unwrap(),
+            "2023-03-24T10:15:44Z ERROR: AcceptBlockHeader: prev block not found"
+        );
+        assert!(first_unexpected_error(lines(), &["ReadBlockFromDisk", "prev block"]).is_none());
+    }
+
+    #[test]
+    fn test_assert_no_log_errors() {
         let exe = init();
         let bitcoind = BitcoinD::new(exe).unwrap();
-        let info = bitcoind.client.get_blockchain_info().unwrap();
-        assert_eq!(0, info.blocks);
         let address = bitcoind.client.get_new_address(None, None).unwrap();
-        let _ = bitcoind.client.generate_to_address(1, &address).unwrap();
-        let info = bitcoind.client.get_blockchain_info().unwrap();
-        assert_eq!(1, info.blocks);
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        bitcoind.assert_no_log_errors(&[]).unwrap();
+    }
+
+    #[test]
+    fn test_partitioned_pair() {
+        use crate::wait_for;
+        use std::time::Duration;
+        let exe = init();
+        let (a, b) = BitcoinD::partitioned_pair(&exe, 3, 5).unwrap();
+        assert_eq!(a.client.get_block_count().unwrap(), 3);
+        assert_eq!(b.client.get_block_count().unwrap(), 5);
+        assert!(a.assert_same_tip_as(&b).is_err());
+        assert_eq!(peers_connected(&a.client), 0);
+
+        a.connect_to(&b).unwrap();
+        wait_for(Duration::from_secs(10), || {
+            Ok(if a.assert_same_tip_as(&b).is_ok() {
+                Some(())
+            } else {
+                None
+            })
+        })
+        .unwrap();
+        assert_eq!(a.client.get_block_count().unwrap(), 5);
     }
 
     #[test]
-    #[cfg(any(feature = "0_21_0", feature = "0_21_1"))]
-    fn test_getindexinfo() {
+    fn test_drop_clean_shutdown() {
         let exe = init();
+        let datadir = TempDir::new().unwrap();
         let mut conf = Conf::default();
-        conf.args.push("-txindex");
+        conf.staticdir = Some(datadir.path().to_path_buf());
         let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
-        assert!(
-            bitcoind.client.version().unwrap() >= 210_000,
-            "getindexinfo requires bitcoin >0.21"
-        );
-        let info: std::collections::HashMap<String, bitcoincore_rpc::jsonrpc::serde_json::Value> =
-            bitcoind.client.call("getindexinfo", &[]).unwrap();
-        assert!(info.contains_key("txindex"));
-        assert!(bitcoind.client.version().unwrap() >= 210_000);
+        let log_path = bitcoind.debug_log_path();
+        drop(bitcoind);
+        let log = fs::read_to_string(log_path).unwrap();
+        assert!(log.trim_end().ends_with("Shutdown: done"));
     }
 
     #[test]
-    fn test_p2p() {
+    fn test_wallet_loaded_at_startup() {
         let exe = init();
+        let datadir = TempDir::new().unwrap();
         let mut conf = Conf::default();
-        conf.p2p = P2P::Yes;
-
+        conf.staticdir = Some(datadir.path().to_path_buf());
         let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
-        assert_eq!(peers_connected(&bitcoind.client), 0);
-        let mut other_conf = Conf::default();
-        other_conf.p2p = bitcoind.p2p_connect(false).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        drop(bitcoind);
 
-        let other_bitcoind = BitcoinD::with_conf(&exe, &other_conf).unwrap();
-        assert_eq!(peers_connected(&bitcoind.client), 1);
-        assert_eq!(peers_connected(&other_bitcoind.client), 1);
+        // the wallet is already loaded when the node is ready
+        conf.args.push("-wallet=default");
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let info = bitcoind.client.get_address_info(&address).unwrap();
+        assert!(info.is_mine.unwrap());
     }
 
     #[test]
-    fn test_data_persistence() {
-        // Create a Conf with staticdir type
+    fn test_no_wallet() {
+        let exe = init();
         let mut conf = Conf::default();
-        let datadir = TempDir::new().unwrap();
-        conf.staticdir = Some(datadir.path().to_path_buf());
+        conf.wallet = None;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(bitcoind.client.get_blockchain_info().unwrap().blocks, 0);
+        assert!(bitcoind.client.list_wallets().unwrap().is_empty());
 
-        // Start BitcoinD with persistent db config
-        // Generate 101 blocks
-        // Wallet balance should be 50
-        let bitcoind = BitcoinD::with_conf(exe_path().unwrap(), &conf).unwrap();
-        let core_addrs = bitcoind.client.get_new_address(None, None).unwrap();
-        bitcoind
-            .client
-            .generate_to_address(101, &core_addrs)
-            .unwrap();
-        let wallet_balance_1 = bitcoind.client.get_balance(None, None).unwrap();
-        let best_block_1 = bitcoind.client.get_best_block_hash().unwrap();
+        conf.wallet = Some("alice");
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(bitcoind.client.list_wallets().unwrap(), vec!["alice"]);
+        bitcoind.client.get_new_address(None, None).unwrap();
+    }
 
+    #[test]
+    fn test_read_only_snapshot() {
+        let exe = init();
+        let snapshot = TempDir::new().unwrap();
+        let mut conf = Conf::default();
+        conf.staticdir = Some(snapshot.path().to_path_buf());
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(5, &address).unwrap();
         drop(bitcoind);
 
-        // Start a new BitcoinD with the same datadir
-        let bitcoind = BitcoinD::with_conf(exe_path().unwrap(), &conf).unwrap();
-
-        let wallet_balance_2 = bitcoind.client.get_balance(None, None).unwrap();
-        let best_block_2 = bitcoind.client.get_best_block_hash().unwrap();
-
-        // Check node chain data persists
-        assert_eq!(best_block_1, best_block_2);
+        let mut conf = Conf::default();
+        conf.copy_datadir_from = Some(snapshot.path().to_path_buf());
+        conf.read_only = true;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_ne!(bitcoind.workdir(), snapshot.path());
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 5);
+        assert!(bitcoind.client.list_wallets().is_err(), "wallet disabled");
+        drop(bitcoind);
 
-        // Check the node wallet balance persists
-        assert_eq!(wallet_balance_1, wallet_balance_2);
+        // the network subdirectory can be given too
+        let mut conf = Conf::default();
+        conf.copy_datadir_from = Some(snapshot.path().join("regtest"));
+        conf.startup.wait_for_full_sync = true;
+        let bitcoind = BitcoinD::with_conf(&exe, &conf).unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 5);
     }
 
     #[test]
@@ -639,6 +5553,16 @@ mod test {
         assert_eq!(node3_peers, 1, "listen false but more than 1 peer");
     }
 
+    #[test]
+    fn test_client_for_path() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let node = bitcoind.client_for_path("").unwrap();
+        assert_eq!(node.get_block_count().unwrap(), 0);
+        let wallet = bitcoind.client_for_path("/wallet/default").unwrap();
+        wallet.get_new_address(None, None).unwrap();
+    }
+
     #[cfg(not(any(feature = "0_17_1", feature = "0_18_0", feature = "0_18_1")))]
     #[test]
     fn test_multi_wallet() {
@@ -701,6 +5625,17 @@ mod test {
             bitcoind.create_wallet("bob").is_err(),
             "wallet already exist"
         );
+
+        let bob_again = bitcoind.wallet_client("bob").unwrap();
+        assert_eq!(
+            bob.get_balances().unwrap(),
+            bob_again.get_balances().unwrap()
+        );
+        let err = bitcoind.wallet_client("carol").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::WalletNotLoaded(_))
+        ));
     }
 
     #[test]
@@ -742,6 +5677,27 @@ mod test {
         assert_eq!(1, info.blocks);
     }
 
+    #[test]
+    fn test_rpc_auth_user_pass() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.rpc_auth = RpcAuth::UserPass("user".to_string(), "pass".to_string());
+        let bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+        assert_eq!(
+            bitcoind.params.auth(),
+            Auth::UserPass("user".to_string(), "pass".to_string())
+        );
+        assert_eq!(0, bitcoind.client.get_blockchain_info().unwrap().blocks);
+
+        // the cookie keeps working alongside
+        let client = Client::new(
+            &bitcoind.rpc_url(),
+            Auth::CookieFile(bitcoind.params.cookie_file.clone()),
+        )
+        .unwrap();
+        assert_eq!(0, client.get_blockchain_info().unwrap().blocks);
+    }
+
     #[test]
     fn test_get_cookie_user_and_pass() {
         let exe = init();
@@ -761,6 +5717,382 @@ mod test {
 
         assert_eq!(user, result_values.0.unwrap().as_str());
         assert_eq!(password, result_values.1.unwrap().as_str());
+        let (cookie_user, cookie_password) = bitcoind.rpc_cookie().unwrap();
+        assert_eq!(
+            (cookie_user.as_str(), cookie_password.as_str()),
+            (user, password)
+        );
+
+        fs::remove_file(&bitcoind.params.cookie_file).unwrap();
+        assert!(matches!(
+            bitcoind.rpc_cookie(),
+            Err(Error::CookieNotFound(path)) if path == bitcoind.params.cookie_file
+        ));
+    }
+
+    #[test]
+    fn test_parse_cookie() {
+        use crate::parse_cookie;
+        assert_eq!(
+            parse_cookie("__cookie__:abc:def\n").unwrap(),
+            ("__cookie__".to_string(), "abc:def".to_string())
+        );
+        assert!(parse_cookie("no separator").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_rpc() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.rpc_threads = Some(8);
+        let bitcoind = std::sync::Arc::new(BitcoinD::with_conf(exe, &conf).unwrap());
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let bitcoind = bitcoind.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        assert_eq!(0, bitcoind.client.get_block_count().unwrap());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rpc_work_queue() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.rpc_threads = Some(2);
+        conf.rpc_work_queue = Some(256);
+        let bitcoind = std::sync::Arc::new(BitcoinD::with_conf(exe, &conf).unwrap());
+
+        // a burst of calls exceeding the default queue depth, none is rejected
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(64));
+        let handles: Vec<_> = (0..64)
+            .map(|_| {
+                let bitcoind = bitcoind.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    bitcoind.client.get_block_count()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_write_conf_file() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.write_conf_file = true;
+        conf.p2p = P2P::Yes;
+        let mut bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 0);
+        let content = fs::read_to_string(bitcoind.workdir().join("bitcoin.conf")).unwrap();
+        assert!(content.starts_with("regtest=1\n"));
+        assert!(content.contains(&format!("rpcport={}\n", bitcoind.params.rpc_socket.port())));
+        let p2p_port = bitcoind.params.p2p_socket.unwrap().port();
+        assert!(content.contains(&format!("port={}\n", p2p_port)));
+
+        bitcoind.restart().unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_assert_same_tip_as() {
+        let exe = init();
+        let node1 = BitcoinD::new(&exe).unwrap();
+        let node2 = BitcoinD::new(&exe).unwrap();
+        // regtest nodes share the genesis block
+        node1.assert_same_tip_as(&node2).unwrap();
+
+        let address = node1.client.get_new_address(None, None).unwrap();
+        node1.client.generate_to_address(1, &address).unwrap();
+        let err = node1.assert_same_tip_as(&node2).unwrap_err();
+        assert!(matches!(err, Error::TipMismatch { .. }));
+    }
+
+    #[test]
+    fn test_wait_for_mempool_sync_with() {
+        use crate::wait_for;
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        let exe = init();
+        let (a, b) = BitcoinD::partitioned_pair(&exe, 101, 0).unwrap();
+        // connected before sending, transactions are only announced to already connected peers
+        a.connect_to(&b).unwrap();
+        wait_for(Duration::from_secs(10), || {
+            Ok(if a.assert_same_tip_as(&b).is_ok() {
+                Some(())
+            } else {
+                None
+            })
+        })
+        .unwrap();
+        let address = a.client.get_new_address(None, None).unwrap();
+        a.client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        // transactions are relayed with a random delay of a few seconds
+        a.wait_for_mempool_sync_with(&b, Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(b.client.get_raw_mempool().unwrap().len(), 1);
+
+        // a node not connected never receives the transaction
+        let c = BitcoinD::new(&exe).unwrap();
+        let err = a
+            .wait_for_mempool_sync_with(&c, Duration::from_secs(1))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_timelocked_tx() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let (tx, address) = non_final_tx(&bitcoind);
+        assert!(bitcoind.client.send_raw_transaction(tx.as_str()).is_err());
+
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        bitcoind.client.send_raw_transaction(tx.as_str()).unwrap();
+    }
+
+    #[test]
+    fn test_expect_reject() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let (tx, address) = non_final_tx(&bitcoind);
+
+        bitcoind.expect_reject(&tx, "non-final").unwrap();
+        let err = bitcoind.expect_reject(&tx, "dust").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RejectReasonMismatch { actual, .. } if actual.contains("non-final")
+        ));
+
+        bitcoind.client.generate_to_address(1, &address).unwrap();
+        let err = bitcoind.expect_reject(&tx, "non-final").unwrap_err();
+        assert!(matches!(err, Error::TxAccepted(_)));
+    }
+
+    #[test]
+    fn test_log_event_parse() {
+        let event = LogEvent::parse("2023-03-24T10:15:42Z [net] disconnecting peer=1").unwrap();
+        assert_eq!(event.timestamp, "2023-03-24T10:15:42Z");
+        assert_eq!(event.category.as_deref(), Some("net"));
+        assert_eq!(event.message, "disconnecting peer=1");
+
+        let event = LogEvent::parse(
+            "2023-03-24T10:15:42.123456Z [validation:debug] Enqueuing BlockConnected",
+        )
+        .unwrap();
+        assert_eq!(event.timestamp, "2023-03-24T10:15:42.123456Z");
+        assert_eq!(event.category.as_deref(), Some("validation"));
+        assert_eq!(event.message, "Enqueuing BlockConnected");
+
+        let event = LogEvent::parse("2023-03-24T10:15:42Z Bitcoin Core version v23.0.0").unwrap();
+        assert_eq!(event.category, None);
+        assert_eq!(event.message, "Bitcoin Core version v23.0.0");
+
+        assert!(LogEvent::parse("").is_none());
+        assert!(LogEvent::parse("  continuation of a previous line").is_none());
+    }
+
+    #[test]
+    fn test_log_events() {
+        let exe = init();
+        let mut conf = Conf::default();
+        conf.args.push("-debug=1");
+        let bitcoind = BitcoinD::with_conf(exe, &conf).unwrap();
+        let events: Vec<_> = bitcoind.log_events().unwrap().collect();
+        assert!(events.iter().any(|e| e.message.starts_with("Bitcoin Core")));
+    }
+
+    #[test]
+    fn test_is_tx_in_block() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_btc(1.0).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let previous_tip = bitcoind.client.get_best_block_hash().unwrap();
+        let block_hash = bitcoind.client.generate_to_address(1, &address).unwrap()[0];
+        assert!(bitcoind.is_tx_in_block(&txid, &block_hash).unwrap());
+        assert!(!bitcoind.is_tx_in_block(&txid, &previous_tip).unwrap());
+    }
+
+    #[cfg(not(feature = "0_17_1"))]
+    #[test]
+    fn test_deterministic_address() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.deterministic_address(0).unwrap();
+        assert_eq!(
+            address.to_string(),
+            "bcrt1qp5wfcq48h6d63wyy9qz0awtpfqwwv4sm4gc9mc"
+        );
+        assert_ne!(address, bitcoind.deterministic_address(1).unwrap());
+    }
+
+    #[cfg(not(feature = "0_17_1"))]
+    #[test]
+    fn test_derive_addresses() {
+        use crate::DETERMINISTIC_DESCRIPTOR;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let addresses = bitcoind
+            .derive_addresses(DETERMINISTIC_DESCRIPTOR, Some((0, 2)))
+            .unwrap();
+        assert_eq!(addresses.len(), 3);
+        assert_eq!(
+            addresses[0].to_string(),
+            "bcrt1qp5wfcq48h6d63wyy9qz0awtpfqwwv4sm4gc9mc"
+        );
+
+        let info = bitcoind
+            .client
+            .get_descriptor_info(DETERMINISTIC_DESCRIPTOR)
+            .unwrap();
+        let with_checksum = format!("{}#{}", DETERMINISTIC_DESCRIPTOR, info.checksum);
+        let same = bitcoind
+            .derive_addresses(&with_checksum, Some((0, 2)))
+            .unwrap();
+        assert_eq!(addresses, same);
+    }
+
+    #[test]
+    fn test_coinbase_tx() {
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        let block_hash = bitcoind.client.generate_to_address(1, &address).unwrap()[0];
+        let coinbase = bitcoind.coinbase_tx(&block_hash).unwrap();
+        assert!(coinbase.is_coin_base());
+        let value: u64 = coinbase.output.iter().map(|o| o.value).sum();
+        assert_eq!(value, 50 * 100_000_000);
+    }
+
+    #[test]
+    fn test_get_headers() {
+        use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+        use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+        use bitcoincore_rpc::bitcoin::BlockHeader;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(3, &address).unwrap();
+        let genesis = bitcoind.client.get_block_hash(0).unwrap();
+
+        let hex = bitcoind.get_block_header_hex(&genesis).unwrap();
+        assert_eq!(hex.len(), 160);
+
+        let headers = bitcoind.get_headers(&genesis, 10).unwrap();
+        assert_eq!(headers.len(), 4, "stops at the tip");
+        assert_eq!(headers[0], hex);
+        for (height, header) in headers.iter().enumerate() {
+            let header: BlockHeader = deserialize(&Vec::<u8>::from_hex(header).unwrap()).unwrap();
+            let hash = bitcoind.client.get_block_hash(height as u64).unwrap();
+            assert_eq!(header.block_hash(), hash);
+        }
+        assert_eq!(bitcoind.get_headers(&genesis, 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_psbt() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+
+        let psbt = bitcoind
+            .wallet_create_funded_psbt(&[(address, Amount::from_btc(1.0).unwrap())])
+            .unwrap();
+        assert!(bitcoind.finalize_psbt(&psbt).is_err(), "psbt not signed");
+        let psbt = bitcoind.wallet_process_psbt(&psbt).unwrap();
+        let tx = bitcoind.finalize_psbt(&psbt).unwrap();
+        let txid = bitcoind.client.send_raw_transaction(tx.as_str()).unwrap();
+        assert!(bitcoind.client.get_raw_mempool().unwrap().contains(&txid));
+    }
+
+    #[test]
+    fn test_encrypt_wallet() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        use std::time::Duration;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let amount = Amount::from_btc(1.0).unwrap();
+
+        bitcoind.encrypt_wallet("passphrase").unwrap();
+        let send = || {
+            bitcoind
+                .client
+                .send_to_address(&address, amount, None, None, None, None, None, None)
+        };
+        assert!(send().is_err(), "wallet is locked");
+        assert!(bitcoind
+            .wallet_passphrase("wrong", Duration::from_secs(60))
+            .is_err());
+        bitcoind
+            .wallet_passphrase("passphrase", Duration::from_secs(60))
+            .unwrap();
+        send().unwrap();
+        bitcoind.wallet_lock().unwrap();
+        assert!(send().is_err(), "wallet is locked again");
+    }
+
+    #[test]
+    fn test_decode_raw_transaction() {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let exe = init();
+        let bitcoind = BitcoinD::new(exe).unwrap();
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        let tx = bitcoind
+            .create_timelocked_tx(&address, Amount::from_btc(1.0).unwrap(), 0, None)
+            .unwrap();
+
+        let decoded = bitcoind.decode_raw_transaction(&tx).unwrap();
+        assert_eq!(decoded.size, tx.len() / 2);
+        assert_eq!(decoded.vsize, (decoded.weight + 3) / 4);
+        assert!(decoded
+            .vout
+            .iter()
+            .any(|o| o.value == Amount::from_btc(1.0).unwrap()));
     }
 
     fn peers_connected(client: &Client) -> usize {
@@ -768,6 +6100,20 @@ mod test {
         result.len()
     }
 
+    /// Funds the wallet and returns a transaction, with the address it pays to, which becomes
+    /// final once the next block is mined
+    fn non_final_tx(bitcoind: &BitcoinD) -> (String, Address) {
+        use bitcoincore_rpc::bitcoin::Amount;
+        let address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind.client.generate_to_address(101, &address).unwrap();
+        // a transaction is final when its locktime is lower than the height of the next block
+        let locktime = bitcoind.client.get_block_count().unwrap() as u32 + 1;
+        let tx = bitcoind
+            .create_timelocked_tx(&address, Amount::from_btc(1.0).unwrap(), locktime, None)
+            .unwrap();
+        (tx, address)
+    }
+
     fn init() -> String {
         let _ = env_logger::try_init();
         exe_path().unwrap()
@@ -0,0 +1,217 @@
+//! JSON-RPC transport sending requests through an HTTP proxy, see [crate::Conf::rpc_proxy]
+
+use bitcoincore_rpc::jsonrpc::serde_json::{self, Value};
+use bitcoincore_rpc::jsonrpc::{Request, Response, Transport};
+use bitcoincore_rpc::{jsonrpc, Auth};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Same timeout of the default transport of [bitcoincore_rpc::Client]
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Transport posting the requests to an HTTP proxy, which forwards them to the node
+pub(crate) struct ProxyTransport {
+    /// Address of the proxy as `host:port`
+    proxy: String,
+    /// Absolute URL of the node endpoint, eg. `http://127.0.0.1:18443/wallet/default`
+    url: String,
+    /// Value of the `Authorization` header
+    authorization: Option<String>,
+}
+
+impl ProxyTransport {
+    /// Creates a transport reaching `url` through `proxy`, the credentials are read from `auth`
+    /// now as done by [bitcoincore_rpc::Client::new]
+    pub(crate) fn new(proxy: &str, url: &str, auth: Auth) -> bitcoincore_rpc::Result<Self> {
+        let authorization = match auth.get_user_pass()? {
+            (Some(user), pass) => {
+                let credentials = format!("{}:{}", user, pass.unwrap_or_default());
+                Some(format!("Basic {}", base64(credentials.as_bytes())))
+            }
+            (None, _) => None,
+        };
+        Ok(ProxyTransport {
+            proxy: proxy.to_string(),
+            url: url.to_string(),
+            authorization,
+        })
+    }
+
+    fn post(&self, body: &[u8]) -> Result<Value, jsonrpc::Error> {
+        let response = self.round_trip(body).map_err(transport_error)?;
+        let split = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| transport_error(invalid_data("HTTP response without body")))?;
+        let (head, body) = (&response[..split], &response[split + 4..]);
+        match serde_json::from_slice(body) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                // bitcoind answers errors with a JSON body too, so this is a proxy failure
+                let status = String::from_utf8_lossy(head);
+                let status = status.lines().next().unwrap_or_default();
+                if status.contains(" 200 ") {
+                    Err(e.into())
+                } else {
+                    Err(transport_error(invalid_data(status)))
+                }
+            }
+        }
+    }
+
+    /// Sends `body` to the proxy, returning the whole HTTP response
+    fn round_trip(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = TcpStream::connect(&self.proxy)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        // HTTP/1.0 so that the response is not chunked and ends when the connection is closed
+        let mut request = format!(
+            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            self.url,
+            host(&self.url),
+            body.len()
+        );
+        if let Some(authorization) = &self.authorization {
+            request.push_str(&format!("Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(body)?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        Ok(response)
+    }
+}
+
+impl Transport for ProxyTransport {
+    fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+        Ok(serde_json::from_value(
+            self.post(&serde_json::to_vec(&request)?)?,
+        )?)
+    }
+
+    fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+        Ok(serde_json::from_value(
+            self.post(&serde_json::to_vec(requests)?)?,
+        )?)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} via {}", self.url, self.proxy)
+    }
+}
+
+fn transport_error(e: io::Error) -> jsonrpc::Error {
+    jsonrpc::Error::Transport(Box::new(e))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Returns the `host:port` part of the absolute `url`
+fn host(url: &str) -> &str {
+    let without_scheme = url.splitn(2, "://").nth(1).unwrap_or(url);
+    without_scheme.split('/').next().unwrap_or(without_scheme)
+}
+
+/// Standard base64 encoding with padding, as required by the basic authentication
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity((input.len() + 2) / 3 * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64, host, ProxyTransport};
+    use bitcoincore_rpc::jsonrpc::serde_json::{self, Value};
+    use bitcoincore_rpc::{jsonrpc, Auth, Client, RpcApi};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_proxy_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = listener.local_addr().unwrap().to_string();
+        // a proxy answering on behalf of the node
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let length: usize = head
+                .iter()
+                .find(|h| h.starts_with("Content-Length: "))
+                .map(|h| h["Content-Length: ".len()..].parse().unwrap())
+                .unwrap();
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let response = format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{{\"result\":42,\"error\":null,\"id\":{}}}",
+                request["id"]
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            (head, request)
+        });
+
+        let auth = Auth::UserPass("user".to_string(), "pass".to_string());
+        let transport =
+            ProxyTransport::new(&proxy, "http://127.0.0.1:18443/wallet/default", auth).unwrap();
+        let client = Client::from_jsonrpc(jsonrpc::Client::with_transport(transport));
+        assert_eq!(client.get_block_count().unwrap(), 42);
+
+        let (head, request) = handle.join().unwrap();
+        assert_eq!(
+            head[0],
+            "POST http://127.0.0.1:18443/wallet/default HTTP/1.0"
+        );
+        assert!(head.contains(&"Host: 127.0.0.1:18443".to_string()));
+        assert!(head.contains(&"Authorization: Basic dXNlcjpwYXNz".to_string()));
+        assert_eq!(request["method"], "getblockcount");
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            host("http://127.0.0.1:18443/wallet/default"),
+            "127.0.0.1:18443"
+        );
+        assert_eq!(host("http://127.0.0.1:18443"), "127.0.0.1:18443");
+    }
+}
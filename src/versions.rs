@@ -1,3 +1,10 @@
+/// Versions which can be downloaded by enabling the corresponding feature, the SHA256SUMS of
+/// every version are shipped in the `sha256` directory and checked by the build script
+pub const SUPPORTED_VERSIONS: &[&str] = &[
+    "23.0", "22.0", "0.21.1", "0.21.0", "0.20.1", "0.20.0", "0.19.1", "0.19.0.1", "0.18.1",
+    "0.18.0", "0.17.1",
+];
+
 #[cfg(not(any(
     feature = "23_0",
     feature = "22_0",